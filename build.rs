@@ -21,9 +21,7 @@
 extern crate cc;
 use std::env;
 
-fn main() {
-    println!("cargo::rerun-if-changed=crunch");
-    let is_windows = env::var("CARGO_CFG_WINDOWS").is_ok();
+fn crunch_build(is_windows: bool) -> cc::Build {
     let mut build = cc::Build::new();
     build
         .flag("-fno-strict-aliasing")
@@ -35,5 +33,31 @@ fn main() {
         build.define("WIN32", None);
     }
 
-    build.compile("libcrunch.a");
+    build
+}
+
+fn main() {
+    println!("cargo::rerun-if-changed=crunch");
+    let is_windows = env::var("CARGO_CFG_WINDOWS").is_ok();
+
+    crunch_build(is_windows).compile("libcrunch.a");
+
+    // With the simd feature, x86 targets also get an AVX2 build of the decoder
+    // that is picked at runtime. Its namespaces and entry points are renamed so
+    // it can be linked next to the baseline build. SSE2 and NEON are already
+    // part of the x86_64 and aarch64 baselines.
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    if env::var("CARGO_FEATURE_SIMD").is_ok() && (arch == "x86_64" || arch == "x86") {
+        let mut build = crunch_build(is_windows);
+        if env::var("CARGO_CFG_TARGET_ENV").as_deref() == Ok("msvc") {
+            build.flag("/arch:AVX2");
+        } else {
+            build.flag("-mavx2");
+        }
+        build
+            .define("crnd", "crnd_avx2")
+            .define("crnlib", "crnlib_avx2")
+            .define("CRND_EXPORT_SUFFIX", "_avx2")
+            .compile("libcrunch_avx2.a");
+    }
 }
@@ -3055,6 +3055,10 @@ class crn_unpacker {
   inline const void* get_data() const { return m_pData; }
   inline uint32 get_data_size() const { return m_data_size; }
 
+  inline const crnd::vector<uint32>& get_color_endpoints() const { return m_color_endpoints; }
+  inline const crnd::vector<uint32>& get_color_selectors() const { return m_color_selectors; }
+  inline const crnd::vector<uint16>& get_alpha_endpoints() const { return m_alpha_endpoints; }
+
  private:
   enum { cMagicValue = 0x1EF9CABD };
   uint32 m_magic;
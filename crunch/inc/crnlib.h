@@ -78,7 +78,9 @@ enum crn_format {
 
   cCRNFmtTotal,
 
-  cCRNFmtForceDWORD = 0xFFFFFFFF
+  // Kept within int so crn_format is 4 bytes with every compiler, as the Rust
+  // bindings expect.
+  cCRNFmtForceDWORD = 0x7FFFFFFF
 };
 
 // Various library/file format limits.
@@ -1,31 +1,86 @@
 #include <cstdint>
 #include "crnlib/crn_decomp.cpp"
 
+// Extra instruction set builds append a suffix to the exported names so they
+// can be linked alongside the baseline build.
+#ifdef CRND_EXPORT_SUFFIX
+#define CRND_CONCAT_(a, b) a##b
+#define CRND_CONCAT(a, b) CRND_CONCAT_(a, b)
+#define CRND_EXPORT(name) CRND_CONCAT(name, CRND_EXPORT_SUFFIX)
+#else
+#define CRND_EXPORT(name) name
+#endif
+
 extern "C" {
-bool crnd_get_level_info(const void *pData, crnd::uint32 data_size,
+bool CRND_EXPORT(crnd_get_level_info)(const void *pData, crnd::uint32 data_size,
                          crnd::uint32 level_index,
                          crnd::crn_level_info *pLevel_info) {
   return crnd::crnd_get_level_info(pData, data_size, level_index, pLevel_info);
 }
 
-bool crnd_get_texture_info(const void *pData, crnd::uint32 data_size,
+bool CRND_EXPORT(crnd_get_texture_info)(const void *pData, crnd::uint32 data_size,
                            crnd::crn_texture_info *pTexture_info) {
   return crnd::crnd_get_texture_info(pData, data_size, pTexture_info);
 }
 
-void *crnd_unpack_begin(const void *pData, crnd::uint32 data_size) {
+void *CRND_EXPORT(crnd_unpack_begin)(const void *pData, crnd::uint32 data_size) {
   return crnd::crnd_unpack_begin(pData, data_size);
 }
 
-bool crnd_unpack_end(crnd::crnd_unpack_context pContext) {
+bool CRND_EXPORT(crnd_unpack_end)(crnd::crnd_unpack_context pContext) {
   return crnd::crnd_unpack_end(pContext);
 }
 
-bool crnd_unpack_level(crnd::crnd_unpack_context pContext, void **ppDst,
+bool CRND_EXPORT(crnd_unpack_level)(crnd::crnd_unpack_context pContext, void **ppDst,
                        crnd::uint32 dst_size_in_bytes,
                        crnd::uint32 row_pitch_in_bytes,
                        crnd::uint32 level_index) {
   return crnd::crnd_unpack_level(pContext, ppDst, dst_size_in_bytes,
                                  row_pitch_in_bytes, level_index);
 }
+
+bool CRND_EXPORT(crnd_unpack_level_segmented)(
+    crnd::crnd_unpack_context pContext, const void *pSrc,
+    crnd::uint32 src_size_in_bytes, void **ppDst,
+    crnd::uint32 dst_size_in_bytes, crnd::uint32 row_pitch_in_bytes,
+    crnd::uint32 level_index) {
+  return crnd::crnd_unpack_level_segmented(pContext, pSrc, src_size_in_bytes,
+                                           ppDst, dst_size_in_bytes,
+                                           row_pitch_in_bytes, level_index);
+}
+
+void CRND_EXPORT(crnd_set_memory_callbacks)(crnd::crnd_realloc_func pRealloc,
+                                            crnd::crnd_msize_func pMSize,
+                                            void *pUser_data) {
+  crnd::crnd_set_memory_callbacks(pRealloc, pMSize, pUser_data);
+}
+
+// Exposes one of the palettes decoded by crnd_unpack_begin. DXT color
+// endpoints pack two 5:6:5 colors, DXT color selectors hold sixteen 2-bit
+// indices, and alpha endpoints pack two 8-bit values.
+bool CRND_EXPORT(crnd_get_palette)(crnd::crnd_unpack_context pContext,
+                                   crnd::uint32 which, const void **ppData,
+                                   crnd::uint32 *pCount) {
+  if (!pContext)
+    return false;
+  const crnd::crn_unpacker *p = static_cast<crnd::crn_unpacker *>(pContext);
+  if (!p->is_valid())
+    return false;
+  switch (which) {
+  case 0:
+    *ppData = p->get_color_endpoints().begin();
+    *pCount = p->get_color_endpoints().size();
+    return true;
+  case 1:
+    *ppData = p->get_color_selectors().begin();
+    *pCount = p->get_color_selectors().size();
+    return true;
+  case 2:
+    *ppData = p->get_alpha_endpoints().begin();
+    *pCount = p->get_alpha_endpoints().size();
+    return true;
+  default:
+    return false;
+  }
+}
 }
@@ -0,0 +1,250 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Statistics computed from decoded levels.
+
+use std::cmp::Reverse;
+
+use block;
+use pixels;
+use CrnFormat;
+use LevelInfo;
+
+/// Whether the format is plain DXT1, DXT3 or DXT5, whose alpha (DXT1's
+/// punch-through included) can be read from the blocks.
+fn is_dxt(format: CrnFormat) -> bool {
+    matches!(format, CrnFormat::Dxt1 | CrnFormat::Dxt3 | CrnFormat::Dxt5)
+}
+
+/// Alpha values of a block of a format for which `is_dxt` holds.
+fn block_alpha(format: CrnFormat, block: &[u8]) -> [u8; 16] {
+    match format {
+        CrnFormat::Dxt1 => block::dxt1_alpha(block),
+        CrnFormat::Dxt3 => block::dxt3_alpha(block),
+        _ => block::dxt5_alpha(block),
+    }
+}
+
+/// Calls `f` with the position and data of every block of a decoded level.
+pub(crate) fn for_each_block<F: FnMut(u32, u32, &[u8])>(
+    info: &LevelInfo,
+    decoded: &[u8],
+    mut f: F,
+) {
+    for (bx, by, block) in info.blocks(decoded) {
+        f(bx, by, block);
+    }
+}
+
+/// Which of the 16 texels of block `(bx, by)` lie inside the level, with the
+/// faces of a cubemap stacked as `Blocks` yields them.
+pub(crate) fn texel_mask(info: &LevelInfo, bx: u32, by: u32) -> [bool; 16] {
+    let mut mask = [false; 16];
+    let by = by % info.blocks_y.max(1);
+    for (i, m) in mask.iter_mut().enumerate() {
+        let x = bx * 4 + (i % 4) as u32;
+        let y = by * 4 + (i / 4) as u32;
+        *m = x < info.width && y < info.height;
+    }
+    mask
+}
+
+/// Fraction of the level's texels with alpha above `threshold`, over all
+/// faces.
+pub(crate) fn alpha_coverage(info: &LevelInfo, decoded: &[u8], threshold: u8) -> Option<f32> {
+    let total = u64::from(info.width) * u64::from(info.height) * u64::from(info.faces);
+    let level_size = (info.blocks_x * info.blocks_y * info.bytes_per_block * info.faces) as usize;
+    if !is_dxt(info.format) || total == 0 || decoded.len() < level_size {
+        return None;
+    }
+
+    let mut covered = 0u64;
+    for_each_block(info, decoded, |bx, by, block| {
+        let alpha = block_alpha(info.format, block);
+        let mask = texel_mask(info, bx, by);
+        covered += alpha
+            .iter()
+            .zip(mask.iter())
+            .filter(|&(&a, &inside)| inside && a > threshold)
+            .count() as u64;
+    });
+    Some(covered as f32 / total as f32)
+}
+
+/// Calls `f` with every RGBA texel inside the level. Returns `false` if the
+/// format has no software decoder.
+pub(crate) fn for_each_texel<F: FnMut([u8; 4])>(
+    info: &LevelInfo,
+    decoded: &[u8],
+    mut f: F,
+) -> bool {
+    pixels::for_each_pixel(info, decoded, None, |_, _, rgba| f(rgba))
+}
+
+/// Per-channel histograms of a decoded level.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    /// Counts of each value for the R, G, B and A channels.
+    pub channels: [[u32; 256]; 4],
+    /// Number of texels counted.
+    pub texels: u64,
+    // Texel counts and channel sums per 4:4:4 RGB bucket, for dominant colors.
+    buckets: Vec<(u32, [u64; 4])>,
+}
+
+/// A color and the share of texels close to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DominantColor {
+    pub rgba: [u8; 4],
+    /// Fraction of the level's texels in this color's bucket.
+    pub share: f32,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram {
+            channels: [[0; 256]; 4],
+            texels: 0,
+            buckets: vec![(0, [0; 4]); 4096],
+        }
+    }
+
+    fn add(&mut self, rgba: [u8; 4]) {
+        for (channel, &value) in self.channels.iter_mut().zip(rgba.iter()) {
+            channel[value as usize] += 1;
+        }
+        self.texels += 1;
+
+        let index = (usize::from(rgba[0] >> 4) << 8)
+            | (usize::from(rgba[1] >> 4) << 4)
+            | usize::from(rgba[2] >> 4);
+        let bucket = &mut self.buckets[index];
+        bucket.0 += 1;
+        for (sum, &value) in bucket.1.iter_mut().zip(rgba.iter()) {
+            *sum += u64::from(value);
+        }
+    }
+
+    /// Mean of each channel, rounded.
+    pub fn average_color(&self) -> [u8; 4] {
+        let mut rgba = [0; 4];
+        if self.texels == 0 {
+            return rgba;
+        }
+        for (out, channel) in rgba.iter_mut().zip(self.channels.iter()) {
+            let sum: u64 = channel
+                .iter()
+                .enumerate()
+                .map(|(value, &count)| value as u64 * u64::from(count))
+                .sum();
+            *out = ((sum + self.texels / 2) / self.texels) as u8;
+        }
+        rgba
+    }
+
+    /// Up to `count` of the most common colors, most common first. Colors are
+    /// grouped by their top four bits per RGB channel, and each group is
+    /// reported as its mean color.
+    pub fn dominant_colors(&self, count: usize) -> Vec<DominantColor> {
+        let mut buckets: Vec<&(u32, [u64; 4])> = self.buckets.iter().filter(|b| b.0 > 0).collect();
+        buckets.sort_by_key(|b| Reverse(b.0));
+        buckets
+            .into_iter()
+            .take(count)
+            .map(|&(n, sums)| {
+                let n64 = u64::from(n);
+                let mut rgba = [0; 4];
+                for (out, &sum) in rgba.iter_mut().zip(sums.iter()) {
+                    *out = ((sum + n64 / 2) / n64) as u8;
+                }
+                DominantColor {
+                    rgba,
+                    share: n as f32 / self.texels as f32,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Histogram of a decoded level, or `None` if the format has no software
+/// decoder.
+pub(crate) fn histogram(info: &LevelInfo, decoded: &[u8]) -> Option<Histogram> {
+    let mut histogram = Histogram::new();
+    if for_each_texel(info, decoded, |rgba| histogram.add(rgba)) {
+        Some(histogram)
+    } else {
+        None
+    }
+}
+
+/// Estimates the average color of a DXT1/3/5 texture from its decoded
+/// palettes: the mean of each endpoint pair, blended by the mean selector
+/// weight. Palette entries are weighted equally, however often blocks use
+/// them, so this is a rough placeholder rather than an exact average.
+pub(crate) fn palette_average_color(
+    format: CrnFormat,
+    color_endpoints: &[u32],
+    color_selectors: &[u32],
+    alpha_endpoints: &[u32],
+) -> Option<[u8; 4]> {
+    // Weight of the second endpoint for each 2-bit DXT selector.
+    const WEIGHTS: [f64; 4] = [0.0, 1.0, 1.0 / 3.0, 2.0 / 3.0];
+
+    if !is_dxt(format) || color_endpoints.is_empty() {
+        return None;
+    }
+    let mut low = [0.0; 3];
+    let mut high = [0.0; 3];
+    for &endpoints in color_endpoints {
+        let c0 = block::unpack_565(endpoints as u16);
+        let c1 = block::unpack_565((endpoints >> 16) as u16);
+        for channel in 0..3 {
+            low[channel] += f64::from(c0[channel]);
+            high[channel] += f64::from(c1[channel]);
+        }
+    }
+    let weight = if color_selectors.is_empty() {
+        0.5
+    } else {
+        let total: f64 = color_selectors
+            .iter()
+            .map(|&selectors| {
+                (0..16)
+                    .map(|texel| WEIGHTS[(selectors >> (texel * 2) & 3) as usize])
+                    .sum::<f64>()
+            })
+            .sum();
+        total / (16 * color_selectors.len()) as f64
+    };
+    let count = color_endpoints.len() as f64;
+    let mut rgba = [255; 4];
+    for channel in 0..3 {
+        let value = (low[channel] * (1.0 - weight) + high[channel] * weight) / count;
+        rgba[channel] = value.round() as u8;
+    }
+    if !alpha_endpoints.is_empty() {
+        let total: f64 = alpha_endpoints
+            .iter()
+            .map(|&endpoints| f64::from((endpoints & 0xFF) + (endpoints >> 8 & 0xFF)) / 2.0)
+            .sum();
+        rgba[3] = (total / alpha_endpoints.len() as f64).round() as u8;
+    }
+    Some(rgba)
+}
@@ -0,0 +1,96 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Texture arrays stored as several CRN files back to back, as Unity does for
+//! crunched `Texture2DArray` assets.
+
+use std::slice;
+
+use header::Header;
+use CrunchedData;
+use DecoderOptions;
+
+/// A sequence of crunched images sharing one buffer.
+pub struct CrunchedArray<'a> {
+    slices: Vec<CrunchedData<'a>>,
+}
+
+impl<'a> CrunchedArray<'a> {
+    /// Splits `buffer` into `slice_count` CRN files using the data size in
+    /// each file's header. Returns `None` if a header is invalid or the
+    /// buffer ends before the last slice.
+    pub fn new(buffer: &'a [u8], slice_count: usize) -> Option<Self> {
+        Self::with_options(buffer, slice_count, DecoderOptions::default())
+    }
+
+    /// Like `new`, but every slice decodes with `options`, e.g. to cancel
+    /// the decode of a whole array with one token.
+    pub fn with_options(
+        buffer: &'a [u8],
+        slice_count: usize,
+        options: DecoderOptions,
+    ) -> Option<Self> {
+        let mut slices = Vec::with_capacity(slice_count);
+        let mut rest = buffer;
+        for _ in 0..slice_count {
+            let header = Header::validate(rest).ok()?;
+            if header.data_size < header.header_size {
+                return None;
+            }
+            let (slice, tail) = rest.split_at(header.data_size as usize);
+            slices.push(CrunchedData::with_options(slice, options.clone()));
+            rest = tail;
+        }
+        Some(CrunchedArray { slices })
+    }
+
+    /// Number of slices in the array.
+    pub fn len(&self) -> usize {
+        self.slices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slices.is_empty()
+    }
+
+    /// Returns the slice at `index`.
+    pub fn get(&self, index: usize) -> Option<&CrunchedData<'a>> {
+        self.slices.get(index)
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, CrunchedData<'a>> {
+        self.slices.iter()
+    }
+
+    /// Transcodes the specified mipmap level of every slice, one buffer per
+    /// layer.
+    pub fn decode_level(&self, level: u32) -> Option<Vec<Vec<u8>>> {
+        self.slices.iter().map(|s| s.decode_level(level)).collect()
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'b CrunchedArray<'a> {
+    type Item = &'b CrunchedData<'a>;
+    type IntoIter = slice::Iter<'b, CrunchedData<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.slices.iter()
+    }
+}
@@ -0,0 +1,454 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Software decoding of individual DXT blocks.
+//!
+//! Texels are returned in row-major order, four rows of four.
+
+use CrnFormat;
+use LevelInfo;
+
+/// Iterator over the blocks of a decoded level, in row-major order, yielding
+/// each block's position in blocks and its bytes. The faces of a cubemap
+/// follow one another, so the row keeps counting past `blocks_y` into the
+/// next face.
+#[derive(Debug, Clone)]
+pub struct Blocks<'a> {
+    data: &'a [u8],
+    blocks_x: u32,
+    bytes_per_block: usize,
+    next: u32,
+    end: u32,
+}
+
+impl<'a> Blocks<'a> {
+    pub(crate) fn new(info: &LevelInfo, data: &'a [u8]) -> Blocks<'a> {
+        let bytes_per_block = info.bytes_per_block as usize;
+        let available = match bytes_per_block {
+            0 => 0,
+            size => (data.len() / size) as u32,
+        };
+        Blocks {
+            data,
+            blocks_x: info.blocks_x,
+            bytes_per_block,
+            next: 0,
+            end: (info.blocks_x * info.blocks_y * info.faces).min(available),
+        }
+    }
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = (u32, u32, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.end {
+            return None;
+        }
+        let index = self.next;
+        self.next += 1;
+        let pos = index as usize * self.bytes_per_block;
+        Some((
+            index % self.blocks_x,
+            index / self.blocks_x,
+            &self.data[pos..pos + self.bytes_per_block],
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.end - self.next) as usize;
+        (len, Some(len))
+    }
+}
+
+impl<'a> ExactSizeIterator for Blocks<'a> {}
+
+/// Alpha values of a DXT1 block: 0 for the transparent index of a
+/// three-color block, 255 otherwise.
+pub(crate) fn dxt1_alpha(block: &[u8]) -> [u8; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let mut alpha = [255; 16];
+    if c0 <= c1 {
+        let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+        for (i, a) in alpha.iter_mut().enumerate() {
+            if (indices >> (2 * i)) & 3 == 3 {
+                *a = 0;
+            }
+        }
+    }
+    alpha
+}
+
+/// Explicit 4-bit alpha of a DXT3 block.
+pub(crate) fn dxt3_alpha(block: &[u8]) -> [u8; 16] {
+    let mut alpha = [0; 16];
+    for (i, a) in alpha.iter_mut().enumerate() {
+        let nibble = (block[i / 2] >> (4 * (i % 2))) & 0xF;
+        *a = nibble * 17;
+    }
+    alpha
+}
+
+/// Values of a DXT5 alpha block (also used by DXT5A and DXN), with crunch's
+/// interpolation.
+pub(crate) fn dxt5_alpha(block: &[u8]) -> [u8; 16] {
+    let l = u32::from(block[0]);
+    let h = u32::from(block[1]);
+    let values: [u32; 8] = if l > h {
+        [
+            l,
+            h,
+            (l * 6 + h) / 7,
+            (l * 5 + h * 2) / 7,
+            (l * 4 + h * 3) / 7,
+            (l * 3 + h * 4) / 7,
+            (l * 2 + h * 5) / 7,
+            (l + h * 6) / 7,
+        ]
+    } else {
+        [
+            l,
+            h,
+            (l * 4 + h) / 5,
+            (l * 3 + h * 2) / 5,
+            (l * 2 + h * 3) / 5,
+            (l + h * 4) / 5,
+            0,
+            255,
+        ]
+    };
+
+    let indices = block[2..8]
+        .iter()
+        .rev()
+        .fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+    let mut alpha = [0; 16];
+    for (i, a) in alpha.iter_mut().enumerate() {
+        *a = values[((indices >> (3 * i)) & 7) as usize] as u8;
+    }
+    alpha
+}
+
+/// Expands a packed 5:6:5 color to 8 bits per channel.
+pub(crate) fn unpack_565(packed: u16) -> [u32; 3] {
+    let r = u32::from(packed >> 11) & 31;
+    let g = u32::from(packed >> 5) & 63;
+    let b = u32::from(packed) & 31;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+/// Colors of a DXT1 color block, with crunch's interpolation. DXT3 and DXT5
+/// color blocks always use the four-color mode.
+fn dxt_colors(block: &[u8], four_color_only: bool) -> [[u8; 4]; 16] {
+    let packed0 = u16::from_le_bytes([block[0], block[1]]);
+    let packed1 = u16::from_le_bytes([block[2], block[3]]);
+    let c0 = unpack_565(packed0);
+    let c1 = unpack_565(packed1);
+
+    let mut palette = [[0u8; 4]; 4];
+    for ch in 0..3 {
+        palette[0][ch] = c0[ch] as u8;
+        palette[1][ch] = c1[ch] as u8;
+        if four_color_only || packed0 > packed1 {
+            palette[2][ch] = ((c0[ch] * 2 + c1[ch]) / 3) as u8;
+            palette[3][ch] = ((c1[ch] * 2 + c0[ch]) / 3) as u8;
+        } else {
+            palette[2][ch] = ((c0[ch] + c1[ch]) >> 1) as u8;
+        }
+    }
+    palette[0][3] = 255;
+    palette[1][3] = 255;
+    palette[2][3] = 255;
+    if four_color_only || packed0 > packed1 {
+        palette[3][3] = 255;
+    }
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut texels = [[0; 4]; 16];
+    for (i, t) in texels.iter_mut().enumerate() {
+        *t = palette[((indices >> (2 * i)) & 3) as usize];
+    }
+    texels
+}
+
+/// Converts a texel of crnlib's CCxY layout (Cb + 123 in red, Cr + 125 in
+/// green, luma in alpha) to opaque RGB. Despite the name, crnlib stores JFIF
+/// YCbCr rather than YCoCg; this is its `YCC_to_RGB`, fixed point included.
+pub(crate) fn ccxy_to_rgb(texel: [u8; 4]) -> [u8; 4] {
+    let y = i32::from(texel[3]);
+    let cb = i32::from(texel[0]) - 123;
+    let cr = i32::from(texel[1]) - 125;
+    let clamp = |v: i32| v.clamp(0, 255) as u8;
+    [
+        clamp(y + ((91881 * cr + 32768) >> 16)),
+        clamp(y + ((-46802 * cr - 22554 * cb + 32768) >> 16)),
+        clamp(y + ((116130 * cb + 32768) >> 16)),
+        255,
+    ]
+}
+
+/// crunch's `g_etc1_inten_tables`: the two modifier magnitudes of each ETC1
+/// intensity table.
+const ETC1_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Texels of an ETC1 block, which crunch stores as a big-endian 64-bit value.
+fn etc1_colors(block: &[u8]) -> [[u8; 4]; 16] {
+    let diff = block[3] & 2 != 0;
+    let flip = block[3] & 1 != 0;
+    let mut bases = [[0i32; 3]; 2];
+    for ch in 0..3 {
+        let byte = i32::from(block[ch]);
+        if diff {
+            let c0 = byte >> 3;
+            let delta = ((byte & 7) << 29) >> 29;
+            let c1 = (c0 + delta) & 31;
+            bases[0][ch] = (c0 << 3) | (c0 >> 2);
+            bases[1][ch] = (c1 << 3) | (c1 >> 2);
+        } else {
+            bases[0][ch] = (byte >> 4) * 17;
+            bases[1][ch] = (byte & 15) * 17;
+        }
+    }
+    let tables = [
+        ETC1_MODIFIERS[usize::from(block[3] >> 5)],
+        ETC1_MODIFIERS[usize::from((block[3] >> 2) & 7)],
+    ];
+    let indices = u32::from_be_bytes([block[4], block[5], block[6], block[7]]);
+
+    let mut texels = [[0; 4]; 16];
+    for (i, t) in texels.iter_mut().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        let sub = if flip { y / 2 } else { x / 2 };
+        // Indices are stored column by column, high bits in the top half.
+        let bit = x * 4 + y;
+        let index = ((indices >> (bit + 16)) & 1) << 1 | ((indices >> bit) & 1);
+        let [small, large] = tables[sub];
+        let modifier = [small, large, -small, -large][index as usize];
+        for ch in 0..3 {
+            t[ch] = (bases[sub][ch] + modifier).clamp(0, 255) as u8;
+        }
+        t[3] = 255;
+    }
+    texels
+}
+
+/// RGBA texels of a block, or `None` for formats without a software decoder.
+pub(crate) fn decode_rgba(format: CrnFormat, block: &[u8]) -> Option<[[u8; 4]; 16]> {
+    let (mut texels, alpha) = match format {
+        CrnFormat::Dxt1 => return Some(dxt_colors(block, false)),
+        CrnFormat::Etc1 | CrnFormat::Etc1S => return Some(etc1_colors(block)),
+        CrnFormat::Dxt5A => return Some(dxt5_alpha(block).map(|v| [v, v, v, 255])),
+        CrnFormat::DxNXy | CrnFormat::DxNYx => {
+            let (first, second) = (dxt5_alpha(block), dxt5_alpha(&block[8..]));
+            // Like BC5, X goes to red and Y to green.
+            let (x, y) = if format == CrnFormat::DxNXy {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            let mut texels = [[0, 0, 0, 255]; 16];
+            for (i, t) in texels.iter_mut().enumerate() {
+                t[0] = x[i];
+                t[1] = y[i];
+            }
+            return Some(texels);
+        }
+        CrnFormat::Dxt3 => (dxt_colors(&block[8..], true), dxt3_alpha(block)),
+        CrnFormat::Dxt5
+        | CrnFormat::Dxt5cCxY
+        | CrnFormat::Dxt5xGxR
+        | CrnFormat::Dxt5xGBR
+        | CrnFormat::Dxt5Agbr => (dxt_colors(&block[8..], true), dxt5_alpha(block)),
+        _ => return None,
+    };
+    for (t, &a) in texels.iter_mut().zip(alpha.iter()) {
+        t[3] = a;
+        if format == CrnFormat::Dxt5cCxY {
+            *t = ccxy_to_rgb(*t);
+        }
+    }
+    Some(texels)
+}
+
+/// Reverses the rows of texel indices in a DXT1 color block.
+fn flip_color(block: &mut [u8]) {
+    block[4..8].reverse();
+}
+
+/// Reverses the rows of 3-bit indices in a DXT5 alpha block.
+fn flip_alpha(block: &mut [u8]) {
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    let mut flipped = 0;
+    for row in 0..4 {
+        flipped |= ((indices >> (12 * row)) & 0xFFF) << (12 * (3 - row));
+    }
+    block[2..8].copy_from_slice(&flipped.to_le_bytes()[..6]);
+}
+
+/// Mirrors a block vertically in place. Returns `false` for formats whose
+/// blocks cannot be flipped losslessly, which are the ETC formats.
+pub(crate) fn flip_block(format: CrnFormat, block: &mut [u8]) -> bool {
+    match format {
+        CrnFormat::Dxt1 => flip_color(block),
+        CrnFormat::Dxt3 => {
+            block.swap(0, 6);
+            block.swap(1, 7);
+            block.swap(2, 4);
+            block.swap(3, 5);
+            flip_color(&mut block[8..]);
+        }
+        CrnFormat::Dxt5
+        | CrnFormat::Dxt5cCxY
+        | CrnFormat::Dxt5xGxR
+        | CrnFormat::Dxt5xGBR
+        | CrnFormat::Dxt5Agbr => {
+            flip_alpha(block);
+            flip_color(&mut block[8..]);
+        }
+        CrnFormat::DxNXy | CrnFormat::DxNYx => {
+            flip_alpha(block);
+            flip_alpha(&mut block[8..]);
+        }
+        CrnFormat::Dxt5A => flip_alpha(block),
+        _ => return false,
+    }
+    true
+}
+
+/// Byte offset and bits per texel of each field of texel indices in a block,
+/// or `None` for the ETC formats, whose texels cannot be moved one by one.
+fn index_fields(format: CrnFormat) -> Option<&'static [(usize, usize)]> {
+    match format {
+        CrnFormat::Dxt1 => Some(&[(4, 2)]),
+        CrnFormat::Dxt3 => Some(&[(0, 4), (12, 2)]),
+        CrnFormat::Dxt5
+        | CrnFormat::Dxt5cCxY
+        | CrnFormat::Dxt5xGxR
+        | CrnFormat::Dxt5xGBR
+        | CrnFormat::Dxt5Agbr => Some(&[(2, 3), (12, 2)]),
+        CrnFormat::DxNXy | CrnFormat::DxNYx => Some(&[(2, 3), (10, 3)]),
+        CrnFormat::Dxt5A => Some(&[(2, 3)]),
+        _ => None,
+    }
+}
+
+/// Reads the 16 indices of `bits` bits each that start at byte `ofs`.
+fn read_indices(block: &[u8], ofs: usize, bits: usize) -> u64 {
+    block[ofs..ofs + 2 * bits]
+        .iter()
+        .enumerate()
+        .fold(0, |v, (i, &b)| v | u64::from(b) << (8 * i))
+}
+
+fn write_indices(block: &mut [u8], ofs: usize, bits: usize, indices: u64) {
+    for (i, b) in block[ofs..ofs + 2 * bits].iter_mut().enumerate() {
+        *b = (indices >> (8 * i)) as u8;
+    }
+}
+
+/// Rearranges the texels of one face of a decoded level from `src` into
+/// `dst`, both with rows of blocks `pitch` bytes apart. Texel `(x, y)` of
+/// `dst` is taken from texel `source(x, y)` of `src`, and each block keeps the
+/// endpoints of the block its first texel comes from. The result is exact
+/// when every block draws from a single block, as it does for flips and
+/// quarter turns of faces whose sides are multiples of 4 or below 4. Returns
+/// `false` for the ETC formats.
+pub(crate) fn remap_texels<F: Fn(u32, u32) -> (u32, u32)>(
+    info: &LevelInfo,
+    src: &[u8],
+    dst: &mut [u8],
+    pitch: usize,
+    source: F,
+) -> bool {
+    let fields = match index_fields(info.format) {
+        Some(fields) => fields,
+        None => return false,
+    };
+    let bpb = info.bytes_per_block as usize;
+    let src_block = |x: u32, y: u32| {
+        let ofs = (y / 4) as usize * pitch + (x / 4) as usize * bpb;
+        &src[ofs..ofs + bpb]
+    };
+    for by in 0..info.blocks_y {
+        for bx in 0..info.blocks_x {
+            let ofs = by as usize * pitch + bx as usize * bpb;
+            let block = &mut dst[ofs..ofs + bpb];
+            let (x0, y0) = source(bx * 4, by * 4);
+            block.copy_from_slice(src_block(x0, y0));
+            for &(field, bits) in fields {
+                let mask = (1 << bits) - 1;
+                let mut indices = read_indices(block, field, bits);
+                for i in 0..16 {
+                    let (x, y) = (bx * 4 + i % 4, by * 4 + i / 4);
+                    if x >= info.width || y >= info.height {
+                        continue;
+                    }
+                    let (sx, sy) = source(x, y);
+                    let from = (sy % 4 * 4 + sx % 4) as usize * bits;
+                    let index = (read_indices(src_block(sx, sy), field, bits) >> from) & mask;
+                    let to = i as usize * bits;
+                    indices = (indices & !(mask << to)) | (index << to);
+                }
+                write_indices(block, field, bits, indices);
+            }
+        }
+    }
+    true
+}
+
+/// Mirrors a decoded level with rows of blocks `pitch` bytes apart vertically
+/// by reversing the rows and flipping each block. Returns `false`, leaving
+/// `data` untouched, for formats `flip_block` does not support.
+pub(crate) fn flip_level(info: &LevelInfo, data: &mut [u8], pitch: usize) -> bool {
+    if !flip_block(info.format, &mut [0; 16]) {
+        return false;
+    }
+    let rows = info.blocks_y as usize;
+    let row_len = (info.blocks_x * info.bytes_per_block) as usize;
+    let faces = info.faces.max(1) as usize;
+    for face in data.chunks_mut(rows * pitch).take(faces) {
+        for row in 0..rows / 2 {
+            let (top, bottom) = face.split_at_mut((rows - 1 - row) * pitch);
+            top[row * pitch..(row + 1) * pitch].swap_with_slice(&mut bottom[..pitch]);
+        }
+        for row in face.chunks_mut(pitch).take(rows) {
+            for block in row[..row_len].chunks_mut(info.bytes_per_block as usize) {
+                flip_block(info.format, block);
+            }
+        }
+    }
+    true
+}
@@ -0,0 +1,94 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Version information and checks for files using features the bundled
+//! decoder does not know about.
+
+use std::fmt;
+
+use header::{self, Header};
+
+/// Version of the crunch decoder compiled into this crate (`CRND_LIB_VERSION`).
+pub const DECODER_VERSION: u32 = 104;
+
+/// `DECODER_VERSION` as formatted by crunch (`CRND_VERSION_STRING`).
+pub const DECODER_VERSION_STRING: &str = "01.04";
+
+/// Number of formats understood by the bundled decoder (`cCRNFmtTotal`).
+pub(crate) const FORMAT_COUNT: u32 = 15;
+
+/// A file feature that the bundled decoder may not handle correctly.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompatWarning {
+    /// The format id is newer than any the decoder knows.
+    UnknownFormat(u32),
+    /// Header flags other than the ones the decoder knows are set.
+    UnknownFlags(u32),
+    /// Level data lives outside the file; `decode_level` cannot unpack it.
+    Segmented,
+    /// The header is larger than the decoder's, so a newer writer may have
+    /// stored fields that will be ignored.
+    ExtendedHeader { header_size: u32 },
+}
+
+impl fmt::Display for CompatWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CompatWarning::UnknownFormat(format) => write!(
+                f,
+                "format {} is not supported by decoder {}",
+                format, DECODER_VERSION_STRING
+            ),
+            CompatWarning::UnknownFlags(flags) => write!(f, "unknown header flags {:#06x}", flags),
+            CompatWarning::Segmented => write!(f, "level data is stored in a separate segment"),
+            CompatWarning::ExtendedHeader { header_size } => write!(
+                f,
+                "header is {} bytes, decoder {} expects {}",
+                header_size,
+                DECODER_VERSION_STRING,
+                header::HEADER_SIZE
+            ),
+        }
+    }
+}
+
+pub(crate) fn check(header: &Header) -> Vec<CompatWarning> {
+    let mut warnings = Vec::new();
+    if header.format >= FORMAT_COUNT {
+        warnings.push(CompatWarning::UnknownFormat(header.format));
+    }
+    if header.flags & header::FLAG_SEGMENTED != 0 {
+        warnings.push(CompatWarning::Segmented);
+    }
+    if header.flags & !header::FLAG_SEGMENTED != 0 {
+        warnings.push(CompatWarning::UnknownFlags(
+            header.flags & !header::FLAG_SEGMENTED,
+        ));
+    }
+    // The level offset table follows the fixed fields, so only bytes past it
+    // count as extra header data.
+    let known_size = header::HEADER_SIZE as u32 + 4 * header.levels.saturating_sub(1);
+    if header.header_size > known_size {
+        warnings.push(CompatWarning::ExtendedHeader {
+            header_size: header.header_size,
+        });
+    }
+    warnings
+}
@@ -18,88 +18,297 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use libc::{c_int, c_void};
-use CrunchedData;
+use core::ffi::c_void;
+use std::convert::TryFrom;
+use std::mem;
+use std::ptr;
+use std::slice;
+
+use scratch;
+use sys::*;
+use CrnFormat;
 use LevelInfo;
 use TextureInfo;
 
-extern "C" {
-    fn crnd_get_level_info(
-        pData: *const u8,
-        data_size: u32,
-        level_index: u32,
-        pLevel_info: *mut LevelInfo,
-    ) -> c_int;
-
-    fn crnd_get_texture_info(
-        pData: *const u8,
-        data_size: u32,
-        pTexture_info: *mut TextureInfo,
-    ) -> c_int;
-
-    fn crnd_unpack_begin(pData: *const u8, data_size: u32) -> *const c_void;
-
-    fn crnd_unpack_end(ctx: *const c_void) -> c_int;
-
-    fn crnd_unpack_level(
-        pContext: *const c_void,
-        ppDst: *const *const u8,
-        dst_size_in_bytes: u32,
-        row_pitch_in_bytes: u32,
-        level_index: u32,
-    ) -> c_int;
+// AVX2 build of the unpacking entry points, compiled by build.rs with the simd
+// feature. A context must be used with the build that created it, which holds
+// since the detected CPU features never change during a run.
+#[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+mod avx2 {
+    use core::ffi::c_void;
+    use sys::{MSizeFunc, ReallocFunc};
+
+    extern "C" {
+        pub fn crnd_unpack_begin_avx2(pData: *const u8, data_size: u32) -> *const c_void;
+
+        pub fn crnd_unpack_end_avx2(ctx: *const c_void) -> bool;
+
+        pub fn crnd_unpack_level_avx2(
+            pContext: *const c_void,
+            ppDst: *const *const u8,
+            dst_size_in_bytes: u32,
+            row_pitch_in_bytes: u32,
+            level_index: u32,
+        ) -> bool;
+
+        pub fn crnd_unpack_level_segmented_avx2(
+            pContext: *const c_void,
+            pSrc: *const u8,
+            src_size_in_bytes: u32,
+            ppDst: *const *const u8,
+            dst_size_in_bytes: u32,
+            row_pitch_in_bytes: u32,
+            level_index: u32,
+        ) -> bool;
+
+        pub fn crnd_get_palette_avx2(
+            pContext: *const c_void,
+            which: u32,
+            ppData: *mut *const c_void,
+            pCount: *mut u32,
+        ) -> bool;
+
+        pub fn crnd_set_memory_callbacks_avx2(
+            pRealloc: ReallocFunc,
+            pMSize: MSizeFunc,
+            pUser_data: *mut c_void,
+        );
+    }
+
+    pub fn available() -> bool {
+        is_x86_feature_detected!("avx2")
+    }
 }
 
-pub fn get_level_info(data: &CrunchedData, level: u32) -> LevelInfo {
-    let mut level_info = LevelInfo::default();
-    unsafe {
+pub fn get_level_info(buffer: &[u8], level: u32) -> Option<LevelInfo> {
+    let mut raw = RawLevelInfo {
+        struct_size: mem::size_of::<RawLevelInfo>() as u32,
+        ..Default::default()
+    };
+    let ok = unsafe {
         crnd_get_level_info(
-            data.buffer.as_ptr(),
-            data.buffer.len() as u32,
-            level as u32,
-            &mut level_info as *mut LevelInfo,
-        );
+            buffer.as_ptr(),
+            buffer.len() as u32,
+            level,
+            &mut raw as *mut RawLevelInfo,
+        )
+    };
+    if !ok || raw.struct_size != mem::size_of::<RawLevelInfo>() as u32 {
+        return None;
     }
-    level_info
+    // crunch copies the format from the header without checking it.
+    let format = CrnFormat::try_from(raw.format).ok()?;
+    Some(LevelInfo {
+        struct_size: raw.struct_size,
+        width: raw.width,
+        height: raw.height,
+        faces: raw.faces,
+        blocks_x: raw.blocks_x,
+        blocks_y: raw.blocks_y,
+        bytes_per_block: raw.bytes_per_block,
+        format,
+    })
 }
 
-pub fn get_texture_info(data: &CrunchedData) -> TextureInfo {
-    let mut texture_info = TextureInfo::default();
-    unsafe {
+pub fn get_texture_info(buffer: &[u8]) -> Option<TextureInfo> {
+    let mut raw = RawTextureInfo {
+        struct_size: mem::size_of::<RawTextureInfo>() as u32,
+        ..Default::default()
+    };
+    let ok = unsafe {
         crnd_get_texture_info(
-            data.buffer.as_ptr(),
-            data.buffer.len() as u32,
-            &mut texture_info as *mut TextureInfo,
-        );
+            buffer.as_ptr(),
+            buffer.len() as u32,
+            &mut raw as *mut RawTextureInfo,
+        )
+    };
+    if !ok || raw.struct_size != mem::size_of::<RawTextureInfo>() as u32 {
+        return None;
     }
-    texture_info
+    let format = CrnFormat::try_from(raw.format).ok()?;
+    Some(TextureInfo {
+        struct_size: raw.struct_size,
+        width: raw.width,
+        height: raw.height,
+        levels: raw.levels,
+        faces: raw.faces,
+        bytes_per_block: raw.bytes_per_block,
+        userdata0: raw.userdata0,
+        userdata1: raw.userdata1,
+        format,
+    })
 }
 
 /// Decompresses the texture's decoder tables and endpoint/selector palettes.
 pub fn unpack_begin(buffer: &[u8]) -> *const c_void {
-    unsafe { crnd_unpack_begin(buffer.as_ptr(), buffer.len() as u32) }
+    scratch::install();
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if avx2::available() {
+            return unsafe { avx2::crnd_unpack_begin_avx2(buffer.as_ptr(), buffer.len() as u32) };
+        }
+    }
+    unsafe { raw::crnd_unpack_begin(buffer.as_ptr(), buffer.len() as u32) }
 }
 
+/// Pointers to each of `faces` equal parts of `dst`, which crunch takes as
+/// one destination per face, and the size of a part.
+fn face_pointers(dst: &mut [u8], faces: u32) -> (Vec<*const u8>, u32) {
+    let face_size = dst.len() / faces.max(1) as usize;
+    let ptrs = dst
+        .chunks_mut(face_size.max(1))
+        .take(faces as usize)
+        .map(|face| face.as_ptr())
+        .collect();
+    (ptrs, face_size as u32)
+}
+
+/// Unpacks a level into `dst`, which holds the texture's `faces` faces one
+/// after another.
 pub fn unpack_level(
     ctx: *const c_void,
     dst: &mut [u8],
+    faces: u32,
     row_pitch_in_bytes: u32,
     level_index: u32,
 ) -> bool {
+    let (ptrs, face_size) = face_pointers(dst, faces);
+    if ptrs.len() != faces as usize {
+        return false;
+    }
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if avx2::available() {
+            return unsafe {
+                avx2::crnd_unpack_level_avx2(
+                    ctx,
+                    ptrs.as_ptr(),
+                    face_size,
+                    row_pitch_in_bytes,
+                    level_index,
+                )
+            };
+        }
+    }
     unsafe {
-        let ptr = dst.as_ptr();
         crnd_unpack_level(
             ctx,
-            &ptr as *const *const u8,
-            dst.len() as u32,
-            row_pitch_in_bytes as u32,
-            level_index as u32,
-        ) > 0
+            ptrs.as_ptr(),
+            face_size,
+            row_pitch_in_bytes,
+            level_index,
+        )
     }
 }
 
-pub fn unpack_end(ctx: *const c_void) {
+/// Like `unpack_level`, but reads the level's compressed data from `src`
+/// rather than from the buffer the context was created with.
+pub fn unpack_level_segmented(
+    ctx: *const c_void,
+    src: &[u8],
+    dst: &mut [u8],
+    faces: u32,
+    row_pitch_in_bytes: u32,
+    level_index: u32,
+) -> bool {
+    let (ptrs, face_size) = face_pointers(dst, faces);
+    if ptrs.len() != faces as usize {
+        return false;
+    }
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if avx2::available() {
+            return unsafe {
+                avx2::crnd_unpack_level_segmented_avx2(
+                    ctx,
+                    src.as_ptr(),
+                    src.len() as u32,
+                    ptrs.as_ptr(),
+                    face_size,
+                    row_pitch_in_bytes,
+                    level_index,
+                )
+            };
+        }
+    }
+    unsafe {
+        crnd_unpack_level_segmented(
+            ctx,
+            src.as_ptr(),
+            src.len() as u32,
+            ptrs.as_ptr(),
+            face_size,
+            row_pitch_in_bytes,
+            level_index,
+        )
+    }
+}
+
+/// Frees an unpack context. Returns `false` if it was null or invalid.
+pub fn unpack_end(ctx: *const c_void) -> bool {
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if avx2::available() {
+            return unsafe { avx2::crnd_unpack_end_avx2(ctx) };
+        }
+    }
+    unsafe { crnd_unpack_end(ctx) }
+}
+
+/// Palettes decoded by `unpack_begin`.
+#[derive(Clone, Copy)]
+pub enum Palette {
+    /// Pairs of 5:6:5 colors, first in the low half.
+    ColorEndpoints = 0,
+    /// Sixteen 2-bit DXT indices per entry.
+    ColorSelectors = 1,
+    /// Pairs of 8-bit alpha values, first in the low byte.
+    AlphaEndpoints = 2,
+}
+
+/// Copies a palette out of an unpack context.
+pub fn get_palette(ctx: *const c_void, palette: Palette) -> Option<Vec<u32>> {
+    let mut data: *const c_void = ptr::null();
+    let mut count = 0;
+    let ok = {
+        #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+        {
+            if avx2::available() {
+                unsafe { avx2::crnd_get_palette_avx2(ctx, palette as u32, &mut data, &mut count) }
+            } else {
+                unsafe { crnd_get_palette(ctx, palette as u32, &mut data, &mut count) }
+            }
+        }
+        #[cfg(not(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64"))))]
+        unsafe {
+            crnd_get_palette(ctx, palette as u32, &mut data, &mut count)
+        }
+    };
+    if !ok {
+        return None;
+    }
+    if data.is_null() {
+        return Some(Vec::new());
+    }
+    let count = count as usize;
+    Some(unsafe {
+        match palette {
+            Palette::AlphaEndpoints => slice::from_raw_parts(data as *const u16, count)
+                .iter()
+                .map(|&v| u32::from(v))
+                .collect(),
+            _ => slice::from_raw_parts(data as *const u32, count).to_vec(),
+        }
+    })
+}
+
+/// Routes all of crunch's allocations through the given callbacks.
+pub fn set_memory_callbacks(realloc: ReallocFunc, msize: MSizeFunc) {
+    unsafe {
+        raw::crnd_set_memory_callbacks(realloc, msize, ptr::null_mut());
+    }
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
     unsafe {
-        crnd_unpack_end(ctx);
+        avx2::crnd_set_memory_callbacks_avx2(realloc, msize, ptr::null_mut());
     }
 }
@@ -0,0 +1,252 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Conversion of cubemap faces between API conventions.
+
+use block;
+use DecrunchError;
+use LevelInfo;
+
+/// How a convention stores a face, relative to Direct3D's orientation of the
+/// same face.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaceOrientation {
+    Upright,
+    /// Mirrored left to right.
+    FlippedX,
+    /// Mirrored top to bottom.
+    FlippedY,
+    /// Turned a quarter clockwise.
+    Rotated90,
+    Rotated180,
+    /// Turned a quarter counter-clockwise.
+    Rotated270,
+}
+
+impl FaceOrientation {
+    /// The texel of the upright face shown at `(x, y)` of a `width` by
+    /// `height` face stored in this orientation.
+    fn upright(self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            FaceOrientation::Upright => (x, y),
+            FaceOrientation::FlippedX => (width - 1 - x, y),
+            FaceOrientation::FlippedY => (x, height - 1 - y),
+            FaceOrientation::Rotated90 => (y, width - 1 - x),
+            FaceOrientation::Rotated180 => (width - 1 - x, height - 1 - y),
+            FaceOrientation::Rotated270 => (height - 1 - y, x),
+        }
+    }
+
+    fn inverse(self) -> FaceOrientation {
+        match self {
+            FaceOrientation::Rotated90 => FaceOrientation::Rotated270,
+            FaceOrientation::Rotated270 => FaceOrientation::Rotated90,
+            other => other,
+        }
+    }
+
+    fn is_quarter_turn(self) -> bool {
+        self == FaceOrientation::Rotated90 || self == FaceOrientation::Rotated270
+    }
+}
+
+/// Face order and orientation of a cubemap convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CubeLayout {
+    faces: [(usize, FaceOrientation); 6],
+}
+
+impl CubeLayout {
+    const DIRECT3D: CubeLayout = CubeLayout::uniform([0, 1, 2, 3, 4, 5], FaceOrientation::Upright);
+
+    const fn uniform(order: [usize; 6], orientation: FaceOrientation) -> CubeLayout {
+        CubeLayout {
+            faces: [
+                (order[0], orientation),
+                (order[1], orientation),
+                (order[2], orientation),
+                (order[3], orientation),
+                (order[4], orientation),
+                (order[5], orientation),
+            ],
+        }
+    }
+
+    /// A layout whose slot `i` holds face `faces[i].0` of Direct3D's order
+    /// (+X, -X, +Y, -Y, +Z, -Z), stored in orientation `faces[i].1`. Returns
+    /// `None` unless every face appears exactly once.
+    pub fn new(faces: [(usize, FaceOrientation); 6]) -> Option<CubeLayout> {
+        let mut seen = [false; 6];
+        for &(face, _) in &faces {
+            if face >= 6 || seen[face] {
+                return None;
+            }
+            seen[face] = true;
+        }
+        Some(CubeLayout { faces })
+    }
+
+    pub fn faces(&self) -> [(usize, FaceOrientation); 6] {
+        self.faces
+    }
+}
+
+/// Where a set of cubemap faces comes from or goes to.
+///
+/// Direct3D, OpenGL and Vulkan select cube faces with the same table and
+/// store the first row of each face at the top, so all three use crunch's
+/// layout: +X, -X, +Y, -Y, +Z, -Z, upright. Unity uses the same order but
+/// stores rows bottom to top. Babylon.js loads the faces of a `CubeTexture`
+/// in the order +X, +Y, +Z, -X, -Y, -Z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeConvention {
+    Direct3D,
+    OpenGl,
+    Vulkan,
+    Unity,
+    Babylon,
+    Custom(CubeLayout),
+}
+
+impl CubeConvention {
+    pub fn layout(self) -> CubeLayout {
+        match self {
+            CubeConvention::Direct3D | CubeConvention::OpenGl | CubeConvention::Vulkan => {
+                CubeLayout::DIRECT3D
+            }
+            CubeConvention::Unity => {
+                CubeLayout::uniform([0, 1, 2, 3, 4, 5], FaceOrientation::FlippedY)
+            }
+            CubeConvention::Babylon => {
+                CubeLayout::uniform([0, 2, 4, 1, 3, 5], FaceOrientation::Upright)
+            }
+            CubeConvention::Custom(layout) => layout,
+        }
+    }
+}
+
+/// How to build each slot of `to` from `from`: the slot of `from` holding the
+/// same face, and the orientations of the face in both.
+fn slot_maps(from: CubeLayout, to: CubeLayout) -> [(usize, FaceOrientation, FaceOrientation); 6] {
+    let mut maps = [(0, FaceOrientation::Upright, FaceOrientation::Upright); 6];
+    for (map, &(face, to_orientation)) in maps.iter_mut().zip(&to.faces) {
+        let slot = from.faces.iter().position(|&(f, _)| f == face).unwrap();
+        *map = (slot, from.faces[slot].1, to_orientation);
+    }
+    maps
+}
+
+/// Position in a face stored as `from` of the texel shown at `(x, y)` of the
+/// same face stored as `to`.
+fn locate(
+    from: FaceOrientation,
+    to: FaceOrientation,
+    (x, y): (u32, u32),
+    width: u32,
+    height: u32,
+) -> (u32, u32) {
+    let (x, y) = to.upright(x, y, width, height);
+    from.inverse().upright(x, y, width, height)
+}
+
+/// Converts the six faces of a cubemap level, given as `width` by `height`
+/// pixel images in `from`'s face order and orientation, to `to`'s. Returns
+/// `None` unless there are six faces of equal, pixel-aligned size, and
+/// square ones if a face is turned.
+pub fn remap_faces(
+    faces: &[Vec<u8>],
+    width: u32,
+    height: u32,
+    from: CubeConvention,
+    to: CubeConvention,
+) -> Option<Vec<Vec<u8>>> {
+    let pixels = width as usize * height as usize;
+    if faces.len() != 6 || pixels == 0 {
+        return None;
+    }
+    let len = faces[0].len();
+    if !len.is_multiple_of(pixels) || faces.iter().any(|face| face.len() != len) {
+        return None;
+    }
+
+    let maps = slot_maps(from.layout(), to.layout());
+    let turned = maps
+        .iter()
+        .any(|&(_, from, to)| from.is_quarter_turn() || to.is_quarter_turn());
+    if turned && width != height {
+        return None;
+    }
+    let texel = len / pixels;
+    Some(
+        maps.iter()
+            .map(|&(slot, from, to)| {
+                let mut out = Vec::with_capacity(len);
+                for y in 0..height {
+                    for x in 0..width {
+                        let (sx, sy) = locate(from, to, (x, y), width, height);
+                        let i = (sy * width + sx) as usize * texel;
+                        out.extend_from_slice(&faces[slot][i..i + texel]);
+                    }
+                }
+                out
+            })
+            .collect(),
+    )
+}
+
+/// Converts the faces of a decoded cubemap level, with rows of blocks
+/// `pitch` bytes apart, from crunch's layout to `to`'s. Other levels are left
+/// alone. Turning a face fails with `FlipUnsupported` for the ETC formats.
+pub(crate) fn orient_level(
+    info: &LevelInfo,
+    data: &mut [u8],
+    pitch: usize,
+    to: CubeConvention,
+) -> Result<(), DecrunchError> {
+    let maps = slot_maps(CubeLayout::DIRECT3D, to.layout());
+    let unchanged = maps
+        .iter()
+        .enumerate()
+        .all(|(slot, &(from_slot, _, to))| slot == from_slot && to == FaceOrientation::Upright);
+    if info.faces != 6 || unchanged {
+        return Ok(());
+    }
+    if info.width != info.height {
+        return Err(DecrunchError::OutOfLimits {
+            field: "cubemap height",
+            value: info.height,
+        });
+    }
+
+    let face_size = pitch * info.blocks_y as usize;
+    let original = data[..6 * face_size].to_vec();
+    for (slot, &(from_slot, from, to)) in maps.iter().enumerate() {
+        let src = &original[from_slot * face_size..(from_slot + 1) * face_size];
+        let dst = &mut data[slot * face_size..(slot + 1) * face_size];
+        if to == from {
+            dst.copy_from_slice(src);
+        } else if !block::remap_texels(info, src, dst, pitch, |x, y| {
+            locate(from, to, (x, y), info.width, info.height)
+        }) {
+            return Err(DecrunchError::FlipUnsupported(info.format));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,158 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Decoded levels and textures that describe their own layout.
+
+use std::ops::Range;
+
+use CrnFormat;
+use LevelInfo;
+
+/// A decoded mipmap level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedLevel {
+    pub width: u32,
+    pub height: u32,
+    pub blocks_x: u32,
+    pub blocks_y: u32,
+    /// Bytes from one row of blocks to the next.
+    pub row_pitch: u32,
+    /// Number of faces in `data`, each `row_pitch * blocks_y` bytes and
+    /// stored one after another in the order of the options'
+    /// `CubeConvention` (crunch's/Direct3D's by default).
+    pub faces: u32,
+    pub format: CrnFormat,
+    pub data: Vec<u8>,
+}
+
+impl DecodedLevel {
+    pub(crate) fn new(info: &LevelInfo, data: Vec<u8>) -> DecodedLevel {
+        DecodedLevel {
+            width: info.width,
+            height: info.height,
+            blocks_x: info.blocks_x,
+            blocks_y: info.blocks_y,
+            row_pitch: info.blocks_x * info.bytes_per_block,
+            faces: info.faces,
+            format: info.format,
+            data,
+        }
+    }
+}
+
+impl AsRef<[u8]> for DecodedLevel {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Every level of a texture, as returned by `CrunchedData::decode_all_levels`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedTexture {
+    pub width: u32,
+    pub height: u32,
+    pub faces: u32,
+    pub format: CrnFormat,
+    /// The levels, largest first.
+    pub levels: Vec<DecodedLevel>,
+}
+
+/// Order of the faces and levels of a `PackedTexture`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureLayout {
+    /// Every face of level 0, then every face of level 1, and so on.
+    LevelMajor,
+    /// Every level of face 0, then every level of face 1, and so on.
+    FaceMajor,
+}
+
+impl DecodedTexture {
+    /// Copies every face of every level into one buffer, ordered by
+    /// `layout`.
+    pub fn pack(&self, layout: TextureLayout) -> PackedTexture {
+        let faces = self.faces.max(1);
+        let levels = self.levels.len() as u32;
+        let order: Vec<(u32, u32)> = match layout {
+            TextureLayout::LevelMajor => (0..levels)
+                .flat_map(|level| (0..faces).map(move |face| (face, level)))
+                .collect(),
+            TextureLayout::FaceMajor => (0..faces)
+                .flat_map(|face| (0..levels).map(move |level| (face, level)))
+                .collect(),
+        };
+        let mut data = Vec::new();
+        let mut ranges = vec![0..0; (faces * levels) as usize];
+        for (face, level) in order {
+            let level_data = &self.levels[level as usize].data;
+            let size = level_data.len() / faces as usize;
+            let bytes = &level_data[face as usize * size..(face as usize + 1) * size];
+            ranges[(face * levels + level) as usize] = data.len()..data.len() + bytes.len();
+            data.extend_from_slice(bytes);
+        }
+        PackedTexture {
+            width: self.width,
+            height: self.height,
+            faces,
+            levels,
+            format: self.format,
+            layout,
+            data,
+            ranges,
+        }
+    }
+}
+
+/// Every face and level of a texture in one buffer, as returned by
+/// `CrunchedData::decode_all_packed`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedTexture {
+    pub width: u32,
+    pub height: u32,
+    pub faces: u32,
+    pub levels: u32,
+    pub format: CrnFormat,
+    pub layout: TextureLayout,
+    pub data: Vec<u8>,
+    /// Byte range of each face of each level within `data`, at index
+    /// `face * levels + level` whatever the layout.
+    pub ranges: Vec<Range<usize>>,
+}
+
+impl PackedTexture {
+    /// Byte range of one face of one level within `data`.
+    pub fn range(&self, face: u32, level: u32) -> Option<Range<usize>> {
+        if face >= self.faces || level >= self.levels {
+            return None;
+        }
+        self.ranges
+            .get((face * self.levels + level) as usize)
+            .cloned()
+    }
+}
+
+/// How far `CrunchedData::decode_all_levels_with_progress` has got, reported
+/// after each level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub levels_done: u32,
+    pub levels_total: u32,
+    /// Decoded bytes produced so far.
+    pub bytes_done: usize,
+}
@@ -0,0 +1,83 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Non-fatal anomalies that do not stop a decode but may point at
+//! data-quality problems.
+
+use std::fmt;
+
+use compat::{self, CompatWarning};
+use header::{Header, MAX_LEVELS};
+use strict::{self, Inconsistency};
+use CrunchedData;
+
+/// A non-fatal anomaly reported by [`CrunchedData::diagnostics`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    /// The header could not be parsed; no other checks were run.
+    InvalidHeader,
+    Compat(CompatWarning),
+    /// The buffer extends past the data size recorded in the header.
+    TrailingBytes(usize),
+    /// A level failed one of the strict consistency checks.
+    Level {
+        level: u32,
+        issue: Inconsistency,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Diagnostic::InvalidHeader => write!(f, "invalid CRN header"),
+            Diagnostic::Compat(ref w) => w.fmt(f),
+            Diagnostic::TrailingBytes(count) => {
+                write!(f, "{} trailing bytes after the CRN data", count)
+            }
+            Diagnostic::Level { level, ref issue } => write!(f, "level {}: {}", level, issue),
+        }
+    }
+}
+
+pub(crate) fn collect(c_data: &CrunchedData) -> Vec<Diagnostic> {
+    let header = match Header::parse(c_data.buffer) {
+        Some(h) => h,
+        None => return vec![Diagnostic::InvalidHeader],
+    };
+
+    let mut diagnostics: Vec<Diagnostic> = compat::check(&header)
+        .into_iter()
+        .map(Diagnostic::Compat)
+        .collect();
+
+    let trailing = c_data.buffer.len() - header.data_size as usize;
+    if trailing > 0 {
+        diagnostics.push(Diagnostic::TrailingBytes(trailing));
+    }
+
+    // A corrupt level count can be up to 255; past crunch's limit, every
+    // level would only repeat the same out of range issue.
+    for level in 0..header.levels.min(MAX_LEVELS) {
+        if let Err(issue) = strict::check_level(c_data, level) {
+            diagnostics.push(Diagnostic::Level { level, issue });
+        }
+    }
+    diagnostics
+}
@@ -0,0 +1,158 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! The error type of the fallible decode and info functions.
+
+use std::error;
+use std::fmt;
+
+use compat::DECODER_VERSION_STRING;
+use CrnFormat;
+use Tile;
+
+/// Why a texture or level could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecrunchError {
+    /// The buffer does not start with a valid CRN header.
+    InvalidHeader,
+    /// The buffer ends before the data size given in the header.
+    Truncated {
+        size: usize,
+        expected: usize,
+    },
+    /// A dimension or count in the header is outside what crunch supports.
+    OutOfLimits {
+        field: &'static str,
+        value: u32,
+    },
+    /// The header names a format the bundled decoder does not know.
+    UnsupportedFormat(u32),
+    LevelOutOfRange {
+        level: u32,
+        levels: u32,
+    },
+    FaceOutOfRange {
+        face: u32,
+        faces: u32,
+    },
+    /// The Huffman tables or palettes failed to decode, so no level can be.
+    CorruptTables,
+    /// The level's data is corrupt or truncated.
+    DecodeFailed {
+        level: u32,
+    },
+    /// The requested row pitch is shorter than a row of blocks.
+    PitchTooSmall {
+        pitch: u32,
+        min: u32,
+    },
+    /// The requested row pitch is not a multiple of 4 bytes, as crunch
+    /// requires, or the level does not fit in memory at that pitch.
+    InvalidPitch {
+        pitch: u32,
+    },
+    /// Decoding would allocate more than `DecoderOptions::max_output_size`.
+    OutputTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    /// The decode was cancelled through its `CancelToken`.
+    Cancelled,
+    /// crunch rejected the decoder context when freeing it.
+    CloseFailed,
+    /// The destination buffer cannot hold the decoded level.
+    BufferTooSmall {
+        needed: usize,
+        actual: usize,
+    },
+    /// The tile is not block aligned or extends past the level.
+    InvalidTile(Tile),
+    /// Blocks of this format cannot be flipped or rotated.
+    FlipUnsupported(CrnFormat),
+}
+
+impl fmt::Display for DecrunchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecrunchError::InvalidHeader => write!(f, "invalid CRN header"),
+            DecrunchError::Truncated { size, expected } => {
+                write!(f, "data truncated to {} of {} bytes", size, expected)
+            }
+            DecrunchError::OutOfLimits { field, value } => {
+                write!(f, "{} of {} is outside crunch's limits", field, value)
+            }
+            DecrunchError::UnsupportedFormat(format) => write!(
+                f,
+                "format {} is not supported by decoder {}",
+                format, DECODER_VERSION_STRING
+            ),
+            DecrunchError::LevelOutOfRange { level, levels } => {
+                write!(f, "level {} out of range ({} levels)", level, levels)
+            }
+            DecrunchError::FaceOutOfRange { face, faces } => {
+                write!(f, "face {} out of range ({} faces)", face, faces)
+            }
+            DecrunchError::CorruptTables => write!(f, "tables or palettes are corrupt"),
+            DecrunchError::DecodeFailed { level } => {
+                write!(f, "level {} is corrupt or truncated", level)
+            }
+            DecrunchError::PitchTooSmall { pitch, min } => {
+                write!(f, "row pitch {} is below the minimum of {}", pitch, min)
+            }
+            DecrunchError::InvalidPitch { pitch } => write!(
+                f,
+                "row pitch {} is not a multiple of 4 or too large for the level",
+                pitch
+            ),
+            DecrunchError::OutputTooLarge { size, limit } => {
+                write!(f, "output of {} bytes exceeds the limit of {}", size, limit)
+            }
+            DecrunchError::Cancelled => write!(f, "decoding was cancelled"),
+            DecrunchError::CloseFailed => write!(f, "failed to free the decoder context"),
+            DecrunchError::BufferTooSmall { needed, actual } => write!(
+                f,
+                "destination holds {} bytes but the level needs {}",
+                actual, needed
+            ),
+            DecrunchError::InvalidTile(tile) => write!(
+                f,
+                "{}x{} tile at ({}, {}) is not block aligned or outside the level",
+                tile.width, tile.height, tile.x, tile.y
+            ),
+            DecrunchError::FlipUnsupported(format) => {
+                write!(f, "{} blocks cannot be flipped", format)
+            }
+        }
+    }
+}
+
+impl error::Error for DecrunchError {}
+
+/// A name or value that does not identify a CRN format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFormatError;
+
+impl fmt::Display for ParseFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown CRN format")
+    }
+}
+
+impl error::Error for ParseFormatError {}
@@ -0,0 +1,249 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Field-by-field validation of a CRN file for triaging files that will not
+//! decode.
+
+use std::fmt;
+
+use compat::FORMAT_COUNT;
+use header::{self, read_be, HEADER_SIZE, MAX_RESOLUTION, SIG_VALUE};
+
+/// A header field or data segment that failed validation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    /// Name of the header field, as in crunch's `crn_header`.
+    pub field: &'static str,
+    /// Byte offset of the field within the file.
+    pub offset: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at offset {}: expected {}, found {}",
+            self.field, self.offset, self.expected, self.actual
+        )
+    }
+}
+
+/// Everything found wrong with a file by [`CrunchedData::forensic_report`].
+///
+/// [`CrunchedData::forensic_report`]: ../struct.CrunchedData.html#method.forensic_report
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ForensicReport {
+    pub findings: Vec<Finding>,
+}
+
+impl ForensicReport {
+    /// Whether the file passed every check.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    fn push<E: fmt::Display, A: fmt::Display>(
+        &mut self,
+        field: &'static str,
+        offset: usize,
+        expected: E,
+        actual: A,
+    ) {
+        self.findings.push(Finding {
+            field,
+            offset,
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        });
+    }
+}
+
+impl fmt::Display for ForensicReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_clean() {
+            return write!(f, "no problems found");
+        }
+        for (i, finding) in self.findings.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", finding)?;
+        }
+        Ok(())
+    }
+}
+
+const PALETTES: [(&str, usize); 4] = [
+    ("m_color_endpoints", 33),
+    ("m_color_selectors", 41),
+    ("m_alpha_endpoints", 49),
+    ("m_alpha_selectors", 57),
+];
+
+/// Runs the checks of crunch's `crnd_validate_file`, plus bounds checks on
+/// every segment, and records each failure instead of stopping at the first.
+pub(crate) fn inspect(buffer: &[u8]) -> ForensicReport {
+    let mut report = ForensicReport::default();
+    let len = buffer.len();
+
+    if len < HEADER_SIZE {
+        report.push("file", 0, format!("at least {} bytes", HEADER_SIZE), len);
+        return report;
+    }
+
+    let sig = read_be(buffer, 0, 2);
+    if sig != u32::from(SIG_VALUE) {
+        // Not a CRN file at all; the other fields would be noise.
+        report.push(
+            "m_sig",
+            0,
+            format!("{:#06x}", SIG_VALUE),
+            format!("{:#06x}", sig),
+        );
+        return report;
+    }
+
+    let header_size = read_be(buffer, 2, 2) as usize;
+    let data_size = read_be(buffer, 6, 4) as usize;
+    let levels = read_be(buffer, 16, 1);
+    let header_ok = header_size >= HEADER_SIZE && header_size <= len;
+    let data_ok = data_size <= len && data_size >= header_size;
+
+    if !header_ok {
+        report.push(
+            "m_header_size",
+            2,
+            format!("{} to {}", HEADER_SIZE, len),
+            header_size,
+        );
+    }
+    if !data_ok {
+        report.push(
+            "m_data_size",
+            6,
+            format!("{} to {}", header_size, len),
+            data_size,
+        );
+    }
+
+    if header_ok {
+        let crc = header::crc16(&buffer[6..header_size]);
+        let stored = read_be(buffer, 4, 2);
+        if u32::from(crc) != stored {
+            report.push(
+                "m_header_crc16",
+                4,
+                format!("{:#06x}", crc),
+                format!("{:#06x}", stored),
+            );
+        }
+    }
+    if header_ok && data_ok {
+        let crc = header::crc16(&buffer[header_size..data_size]);
+        let stored = read_be(buffer, 10, 2);
+        if u32::from(crc) != stored {
+            report.push(
+                "m_data_crc16",
+                10,
+                format!("{:#06x}", crc),
+                format!("{:#06x}", stored),
+            );
+        }
+    }
+
+    let width = read_be(buffer, 12, 2);
+    let height = read_be(buffer, 14, 2);
+    for &(field, offset, value) in &[("m_width", 12, width), ("m_height", 14, height)] {
+        if !(1..=MAX_RESOLUTION).contains(&value) {
+            report.push(field, offset, format!("1 to {}", MAX_RESOLUTION), value);
+        }
+    }
+
+    let max_levels = header::max_mips(width, height);
+    if !(1..=max_levels).contains(&levels) {
+        report.push("m_levels", 16, format!("1 to {}", max_levels), levels);
+    }
+
+    let faces = read_be(buffer, 17, 1);
+    if faces != 1 && faces != 6 {
+        report.push("m_faces", 17, "1 or 6", faces);
+    }
+
+    let format = read_be(buffer, 18, 1);
+    if format >= FORMAT_COUNT {
+        report.push("m_format", 18, format!("0 to {}", FORMAT_COUNT - 1), format);
+    }
+
+    let data_end = if data_ok { data_size } else { len };
+
+    for &(field, offset) in &PALETTES {
+        let ofs = read_be(buffer, offset, 3) as usize;
+        let size = read_be(buffer, offset + 3, 3) as usize;
+        let num = read_be(buffer, offset + 6, 2);
+        if num > 0 && (ofs < header_size || ofs + size > data_end) {
+            report.push(
+                field,
+                offset,
+                format!("a segment within {}..{}", header_size, data_end),
+                format!("{}..{}", ofs, ofs + size),
+            );
+        }
+    }
+    if read_be(buffer, 33 + 6, 2) == 0 && read_be(buffer, 49 + 6, 2) == 0 {
+        report.push("m_color_endpoints", 33, "a color or alpha palette", "none");
+    }
+
+    let tables_size = read_be(buffer, 65, 2) as usize;
+    let tables_ofs = read_be(buffer, 67, 3) as usize;
+    if tables_size == 0 {
+        report.push("m_tables_size", 65, "a non-empty table segment", 0);
+    } else if tables_ofs < header_size || tables_ofs + tables_size > data_end {
+        report.push(
+            "m_tables_ofs",
+            67,
+            format!("a segment within {}..{}", header_size, data_end),
+            format!("{}..{}", tables_ofs, tables_ofs + tables_size),
+        );
+    }
+
+    let mut prev_ofs = header_size;
+    for level in 0..levels as usize {
+        let offset = 70 + 4 * level;
+        if offset + 4 > len {
+            report.push("m_level_ofs", offset, "a level offset", "end of file");
+            break;
+        }
+        let ofs = read_be(buffer, offset, 4) as usize;
+        if ofs < prev_ofs || ofs >= data_end {
+            report.push(
+                "m_level_ofs",
+                offset,
+                format!("{} to {}", prev_ofs, data_end.saturating_sub(1)),
+                ofs,
+            );
+        } else {
+            prev_ofs = ofs + 1;
+        }
+    }
+
+    report
+}
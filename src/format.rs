@@ -0,0 +1,77 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Block formats known at compile time, for `CrunchedData::decode_typed`.
+
+use block;
+use CrnFormat;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A block compressed format whose blocks can be decoded in software.
+pub trait Format: sealed::Sealed {
+    /// The crunch format a texture must have to be decoded as this format.
+    const FORMAT: CrnFormat;
+    /// Bytes per block.
+    const BLOCK_SIZE: usize;
+    /// One compressed block.
+    type Block: Copy + AsRef<[u8]>;
+
+    /// Copies a block out of `BLOCK_SIZE` bytes.
+    fn block(bytes: &[u8]) -> Self::Block;
+
+    /// The block's 16 RGBA texels, in row-major order.
+    fn texels(block: &Self::Block) -> [[u8; 4]; 16] {
+        block::decode_rgba(Self::FORMAT, block.as_ref()).unwrap()
+    }
+}
+
+macro_rules! formats {
+    ($($(#[$doc:meta])* $name:ident => $format:ident, $size:expr;)*) => {$(
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy)]
+        pub enum $name {}
+
+        impl sealed::Sealed for $name {}
+
+        impl Format for $name {
+            const FORMAT: CrnFormat = CrnFormat::$format;
+            const BLOCK_SIZE: usize = $size;
+            type Block = [u8; $size];
+
+            fn block(bytes: &[u8]) -> [u8; $size] {
+                let mut block = [0; $size];
+                block.copy_from_slice(bytes);
+                block
+            }
+        }
+    )*};
+}
+
+formats! {
+    /// BC1: 5:6:5 color with optional punch-through alpha.
+    Dxt1 => Dxt1, 8;
+    /// BC2: DXT1 color with explicit 4-bit alpha.
+    Dxt3 => Dxt3, 16;
+    /// BC3: DXT1 color with interpolated alpha.
+    Dxt5 => Dxt5, 16;
+}
@@ -0,0 +1,304 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Minimal reader for the packed, big-endian CRN file header.
+
+use compat;
+use DecrunchError;
+
+/// `'Hx'`, the first two bytes of every CRN file.
+pub(crate) const SIG_VALUE: u16 = 0x4878;
+
+/// Size of the fixed part of the header, up to and including the first level
+/// offset.
+pub(crate) const HEADER_SIZE: usize = 74;
+
+/// The bytes every CRN file starts with.
+pub const CRN_MAGIC: [u8; 2] = *b"Hx";
+
+/// Smallest header size crunch accepts. CRN headers carry no version
+/// number; newer writers may only grow the header, which its size records.
+pub const CRN_MIN_HEADER_SIZE: usize = HEADER_SIZE;
+
+/// Cheaply checks whether `buffer` starts with a CRN header, without
+/// parsing the rest of it or touching crunch.
+pub fn is_crn(buffer: &[u8]) -> bool {
+    buffer.len() >= HEADER_SIZE
+        && buffer[..2] == CRN_MAGIC
+        && read_be(buffer, 2, 2) as usize >= HEADER_SIZE
+}
+
+/// Level data is stored outside the file and must be supplied separately.
+pub(crate) const FLAG_SEGMENTED: u32 = 1;
+
+const LEVEL_OFS_POS: usize = 70;
+
+/// crunch's `cCRNMaxLevelResolution`.
+pub(crate) const MAX_RESOLUTION: u32 = 4096;
+
+/// crunch's `cCRNMaxLevels`.
+pub(crate) const MAX_LEVELS: u32 = 16;
+
+pub(crate) struct Header {
+    pub header_size: u32,
+    pub data_size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub levels: u32,
+    pub faces: u32,
+    pub format: u32,
+    pub flags: u32,
+    /// Offset and size of the color endpoint, color selector, alpha endpoint
+    /// and alpha selector palettes.
+    pub palettes: [(u32, u32); 4],
+    pub tables_size: u32,
+    pub tables_ofs: u32,
+    pub level_ofs: Vec<u32>,
+}
+
+impl Header {
+    /// Parses the header at the start of `buffer`, applying the same checks
+    /// as crunch's `crnd_get_header`.
+    pub fn parse(buffer: &[u8]) -> Option<Header> {
+        Header::parse_prefix(buffer)
+            .filter(|header| buffer.len() as u64 >= u64::from(header.data_size))
+    }
+
+    /// Like `parse`, but says why the header is rejected, and also rejects
+    /// formats the bundled decoder does not know.
+    pub fn validate(buffer: &[u8]) -> Result<Header, DecrunchError> {
+        let header = Header::parse_prefix(buffer).ok_or(DecrunchError::InvalidHeader)?;
+        if (buffer.len() as u64) < u64::from(header.data_size) {
+            return Err(DecrunchError::Truncated {
+                size: buffer.len(),
+                expected: header.data_size as usize,
+            });
+        }
+        header.check()?;
+        Ok(header)
+    }
+
+    /// The checks of `validate` that need only the header, for readers that
+    /// have not loaded the rest of the file.
+    pub(crate) fn check(&self) -> Result<(), DecrunchError> {
+        self.check_limits()?;
+        if self.format >= compat::FORMAT_COUNT {
+            return Err(DecrunchError::UnsupportedFormat(self.format));
+        }
+        if self.flags & FLAG_SEGMENTED == 0 {
+            for level in 0..self.levels {
+                if !self.level_in_bounds(level) {
+                    return Err(DecrunchError::OutOfLimits {
+                        field: "level offset",
+                        value: self.level_ofs[level as usize],
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the limits of crunch's `crnd_validate_file`, which the
+    /// decoding functions themselves skip.
+    fn check_limits(&self) -> Result<(), DecrunchError> {
+        let out_of_limits = |field, value| Err(DecrunchError::OutOfLimits { field, value });
+        if self.width < 1 || self.width > MAX_RESOLUTION {
+            return out_of_limits("width", self.width);
+        }
+        if self.height < 1 || self.height > MAX_RESOLUTION {
+            return out_of_limits("height", self.height);
+        }
+        let max_levels = max_mips(self.width, self.height);
+        if self.levels < 1 || self.levels > max_levels {
+            return out_of_limits("level count", self.levels);
+        }
+        if self.faces != 1 && self.faces != 6 {
+            return out_of_limits("face count", self.faces);
+        }
+        Ok(())
+    }
+
+    /// Like `parse`, but only needs the header itself to be present.
+    pub fn parse_prefix(buffer: &[u8]) -> Option<Header> {
+        if buffer.len() < HEADER_SIZE || read_be(buffer, 0, 2) != u32::from(SIG_VALUE) {
+            return None;
+        }
+
+        let header_size = read_be(buffer, 2, 2);
+        let data_size = read_be(buffer, 6, 4);
+        if (header_size as usize) < HEADER_SIZE {
+            return None;
+        }
+
+        let levels = read_be(buffer, 16, 1);
+        let level_ofs_end = LEVEL_OFS_POS + 4 * levels as usize;
+        if buffer.len() < level_ofs_end {
+            return None;
+        }
+
+        Some(Header {
+            header_size,
+            data_size,
+            width: read_be(buffer, 12, 2),
+            height: read_be(buffer, 14, 2),
+            levels,
+            faces: read_be(buffer, 17, 1),
+            format: read_be(buffer, 18, 1),
+            flags: read_be(buffer, 19, 2),
+            palettes: [33, 41, 49, 57]
+                .map(|pos| (read_be(buffer, pos, 3), read_be(buffer, pos + 3, 3))),
+            tables_size: read_be(buffer, 65, 2),
+            tables_ofs: read_be(buffer, 67, 3),
+            level_ofs: (LEVEL_OFS_POS..level_ofs_end)
+                .step_by(4)
+                .map(|pos| read_be(buffer, pos, 4))
+                .collect(),
+        })
+    }
+
+    /// Compressed size of a level, assuming levels are stored back to back
+    /// and the last one runs to the end of the data.
+    pub fn level_size(&self, level: u32) -> Option<u32> {
+        let start = *self.level_ofs.get(level as usize)?;
+        let end = match self.level_ofs.get(level as usize + 1) {
+            Some(&next) => next,
+            None => self.data_size,
+        };
+        end.checked_sub(start)
+    }
+
+    /// Whether the level's data is non-empty and lies between the end of the
+    /// header and the end of the file's data.
+    pub fn level_in_bounds(&self, level: u32) -> bool {
+        let offset = match self.level_ofs.get(level as usize) {
+            Some(&offset) => offset,
+            None => return false,
+        };
+        offset >= self.header_size
+            && match self.level_size(level) {
+                Some(size) => size > 0 && offset.checked_add(size) <= Some(self.data_size),
+                None => false,
+            }
+    }
+
+    /// Size of everything but the level data: the header, palettes and
+    /// tables. Mirrors crunch's `crnd_get_segmented_file_size`.
+    pub fn base_size(&self) -> u32 {
+        self.palettes
+            .iter()
+            .map(|&(ofs, size)| ofs + size)
+            .chain(Some(self.tables_ofs + self.tables_size))
+            .fold(self.header_size, u32::max)
+    }
+}
+
+/// Every field of a CRN header, as stored in the file. The format has no
+/// version number; `header_size` grows if later writers extend it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrnHeader {
+    pub header_size: u32,
+    pub header_crc16: u16,
+    pub data_size: u32,
+    pub data_crc16: u16,
+    pub width: u32,
+    pub height: u32,
+    pub levels: u32,
+    pub faces: u32,
+    /// Raw `crn_format` value, which may not be one crunch knows.
+    pub format: u32,
+    pub flags: u32,
+    pub userdata0: u32,
+    pub userdata1: u32,
+    /// Offset and size of the color endpoint, color selector, alpha endpoint
+    /// and alpha selector palettes.
+    pub palettes: [(u32, u32); 4],
+    pub tables_ofs: u32,
+    pub tables_size: u32,
+    /// Offset of each mip level's compressed data.
+    pub level_ofs: Vec<u32>,
+}
+
+impl CrnHeader {
+    /// Reads the header at the start of `buffer` without checking its
+    /// contents beyond the signature and size, so damaged files can be
+    /// inspected too.
+    pub fn parse(buffer: &[u8]) -> Option<CrnHeader> {
+        let h = Header::parse_prefix(buffer)?;
+        Some(CrnHeader {
+            header_size: h.header_size,
+            header_crc16: read_be(buffer, 4, 2) as u16,
+            data_size: h.data_size,
+            data_crc16: read_be(buffer, 10, 2) as u16,
+            width: h.width,
+            height: h.height,
+            levels: h.levels,
+            faces: h.faces,
+            format: h.format,
+            flags: h.flags,
+            userdata0: read_be(buffer, 25, 4),
+            userdata1: read_be(buffer, 29, 4),
+            palettes: h.palettes,
+            tables_ofs: h.tables_ofs,
+            tables_size: h.tables_size,
+            level_ofs: h.level_ofs,
+        })
+    }
+
+    /// Whether the level data is stored outside the file.
+    pub fn is_segmented(&self) -> bool {
+        self.flags & FLAG_SEGMENTED != 0
+    }
+}
+
+pub(crate) fn read_be(buffer: &[u8], pos: usize, len: usize) -> u32 {
+    buffer[pos..pos + len]
+        .iter()
+        .fold(0, |acc, &b| (acc << 8) | u32::from(b))
+}
+
+/// crunch's CRC-16, used for the header and data checksums.
+pub(crate) fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = !0;
+    for &b in data {
+        let q = u16::from(b) ^ (crc >> 8);
+        crc <<= 8;
+        let mut r = (q >> 4) ^ q;
+        crc ^= r;
+        r <<= 5;
+        crc ^= r;
+        r <<= 7;
+        crc ^= r;
+    }
+    !crc
+}
+
+/// Number of mip levels in a full chain for the given dimensions.
+pub(crate) fn max_mips(mut width: u32, mut height: u32) -> u32 {
+    if width == 0 && height == 0 {
+        return 0;
+    }
+    let mut mips = 1;
+    while width > 1 || height > 1 {
+        width >>= 1;
+        height >>= 1;
+        mips += 1;
+    }
+    mips
+}
@@ -0,0 +1,53 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Level and face indices checked against a texture.
+
+/// A mipmap level index known to be in range, from `CrunchedData::level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LevelIndex(pub(crate) u32);
+
+impl LevelIndex {
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<LevelIndex> for u32 {
+    fn from(index: LevelIndex) -> u32 {
+        index.0
+    }
+}
+
+/// A face index known to be in range, from `CrunchedData::face`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FaceIndex(pub(crate) u32);
+
+impl FaceIndex {
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<FaceIndex> for u32 {
+    fn from(index: FaceIndex) -> u32 {
+        index.0
+    }
+}
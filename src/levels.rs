@@ -0,0 +1,73 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Iteration over the levels of a texture.
+
+use crunch;
+use CrunchedData;
+use LevelInfo;
+
+/// Iterator over the mipmap levels of a texture, largest first, yielding each
+/// level's `LevelInfo`.
+#[derive(Debug, Clone)]
+pub struct Levels<'a> {
+    buffer: &'a [u8],
+    next: u32,
+    end: u32,
+}
+
+impl<'a> Levels<'a> {
+    pub(crate) fn new(data: &CrunchedData<'a>) -> Levels<'a> {
+        Levels {
+            buffer: data.buffer,
+            next: 0,
+            end: data.level_count(),
+        }
+    }
+}
+
+impl<'a> Iterator for Levels<'a> {
+    type Item = LevelInfo;
+
+    fn next(&mut self) -> Option<LevelInfo> {
+        if self.next == self.end {
+            return None;
+        }
+        let level = self.next;
+        self.next += 1;
+        Some(crunch::get_level_info(self.buffer, level).unwrap_or_default())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.end - self.next) as usize;
+        (len, Some(len))
+    }
+}
+
+impl<'a> ExactSizeIterator for Levels<'a> {}
+
+impl<'a> IntoIterator for &CrunchedData<'a> {
+    type Item = LevelInfo;
+    type IntoIter = Levels<'a>;
+
+    fn into_iter(self) -> Levels<'a> {
+        self.levels()
+    }
+}
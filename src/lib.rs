@@ -25,45 +25,98 @@
 //! # Example
 //!
 //! ```
-//! use decrunch::*;
+//! use decrunch_unity::*;
 //! use std::fs::File;
 //! use std::io::Read;
 //!
 //! # use std::io;
-//! # fn foo() -> io::Result<()> {
-//! let mut compressed_file = File::open("testdata/copyright_2048_compressed.dat")?;
+//! # fn main() -> io::Result<()> {
+//! let mut compressed_file = File::open("testdata/quadrants_dxt1.crn")?;
 //! let mut compressed_data = Vec::new();
 //!
 //! compressed_file.read_to_end(&mut compressed_data)?;
 //!
 //! let c_data = CrunchedData::new(&compressed_data);
-//! let decompressed_data = match c_data.decode_level(0) {
-//!     None => {
-//!         panic!("Failed to decompress texture data");
+//! let decompressed_data = match c_data.try_decode_level(0) {
+//!     Err(err) => {
+//!         panic!("Failed to decompress texture data: {}", err);
 //!     }
-//!     Some(res) => res,
+//!     Ok(res) => res,
 //! };
 //!
-//! assert!(decompressed_data.len() > 0);
+//! // 32x16 texels are 8x4 DXT1 blocks of 8 bytes.
+//! assert_eq!(decompressed_data.len(), 8 * 4 * 8);
 //!
 //! # Ok(())
 //! # }
 //! ```
 
-extern crate libc;
+extern crate core;
 
+mod analysis;
+mod array;
+mod block;
+mod cancel;
+mod compat;
 mod crunch;
+mod cube;
+mod decoded;
+mod diagnostics;
+mod error;
+mod forensics;
+pub mod format;
+mod header;
+mod index;
+mod levels;
+mod library;
+mod lz4;
+mod options;
+mod owned;
+mod pixels;
+mod reader;
+mod recovery;
+mod scratch;
+mod strict;
+pub mod sys;
+pub mod testing;
+mod tile;
+pub mod unity;
 
-use libc::c_void;
+pub use analysis::{DominantColor, Histogram};
+pub use array::CrunchedArray;
+pub use block::Blocks;
+pub use cancel::CancelToken;
+pub use compat::{CompatWarning, DECODER_VERSION, DECODER_VERSION_STRING};
+pub use cube::{remap_faces, CubeConvention, CubeLayout, FaceOrientation};
+pub use decoded::{DecodedLevel, DecodedTexture, PackedTexture, Progress, TextureLayout};
+pub use diagnostics::Diagnostic;
+pub use error::{DecrunchError, ParseFormatError};
+pub use forensics::{Finding, ForensicReport};
+pub use header::{is_crn, CrnHeader, CRN_MAGIC, CRN_MIN_HEADER_SIZE};
+pub use index::{FaceIndex, LevelIndex};
+pub use levels::Levels;
+pub use library::TextureLibrary;
+pub use options::{DecoderOptions, DEFAULT_MAX_OUTPUT_SIZE};
+pub use owned::{CrunchedDataOwned, StableBytes};
+pub use pixels::{ChannelOrder, ColorSpace, Endian, PixelFormat, Planes};
+pub use reader::CrunchedReader;
+pub use recovery::{RecoveredTexture, TruncationLoss};
+pub use scratch::ScratchAllocator;
+pub use strict::Inconsistency;
+pub use tile::Tile;
+
+use core::ffi::c_void;
+use std::convert::TryFrom;
+use std::fmt;
 use std::mem;
+use std::ops::Range;
+use std::ptr;
+use std::str::FromStr;
 
-// Rust with repr(C) actually does 8 bytes here, as does the c compiler on linux
-// For a windows build (at least with cargo xwin), the c compiler will actually
-// use 4 bytes as intended, so we use repr(i32) to match. This is probably
-// the wrong way to fix this, but works and doesn't require changing the c code.
-#[cfg_attr(target_os = "windows", repr(i32))]
-#[cfg_attr(target_os = "linux", repr(C))]
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Texture formats, with the values of crunch's `crn_format`. crunch's
+/// structs carry the format as an `i32`, converted with `as_raw` and
+/// `TryFrom<i32>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[derive(Default)]
 pub enum CrnFormat {
     FirstValid = -2,
@@ -90,29 +143,230 @@ pub enum CrnFormat {
     /// DXT5 alpha blocks only
     Dxt5A,
     Etc1,
+    Etc2,
+    /// ETC2 with EAC alpha
+    Etc2A,
+    /// ETC1 without subblocks, from Unity's fork
+    Etc1S,
+    /// ETC2A without subblocks, from Unity's fork
+    Etc2AS,
     Total,
-    #[cfg(target_os = "linux")] // this doesn't work with repr(i32)
-    ForceDWORD = 0xFFFFFFFF,
 }
 
+/// Names used by crunch's tools, in the order of the formats.
+const FORMAT_NAMES: [(CrnFormat, &str); 15] = [
+    (CrnFormat::Dxt1, "DXT1"),
+    (CrnFormat::Dxt3, "DXT3"),
+    (CrnFormat::Dxt5, "DXT5"),
+    (CrnFormat::Dxt5cCxY, "DXT5_CCxY"),
+    (CrnFormat::Dxt5xGxR, "DXT5_xGxR"),
+    (CrnFormat::Dxt5xGBR, "DXT5_xGBR"),
+    (CrnFormat::Dxt5Agbr, "DXT5_AGBR"),
+    (CrnFormat::DxNXy, "DXN_XY"),
+    (CrnFormat::DxNYx, "DXN_YX"),
+    (CrnFormat::Dxt5A, "DXT5A"),
+    (CrnFormat::Etc1, "ETC1"),
+    (CrnFormat::Etc2, "ETC2"),
+    (CrnFormat::Etc2A, "ETC2A"),
+    (CrnFormat::Etc1S, "ETC1S"),
+    (CrnFormat::Etc2AS, "ETC2AS"),
+];
+
+impl CrnFormat {
+    /// The format's name as used by crunch, such as `"DXT5_xGBR"`, or `None`
+    /// for `Invalid` and the marker values.
+    pub fn name(self) -> Option<&'static str> {
+        FORMAT_NAMES
+            .iter()
+            .find(|&&(format, _)| format == self)
+            .map(|&(_, name)| name)
+    }
+
+    /// Looks up a format by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<CrnFormat> {
+        FORMAT_NAMES
+            .iter()
+            .find(|&&(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|&(format, _)| format)
+    }
+
+    /// The format's `crn_format` value.
+    pub fn as_raw(self) -> i32 {
+        self as i32
+    }
+
+    /// Size of one compressed block, or 0 if this is not an actual format.
+    pub fn bytes_per_block(self) -> u32 {
+        match self {
+            CrnFormat::Dxt1
+            | CrnFormat::Dxt5A
+            | CrnFormat::Etc1
+            | CrnFormat::Etc2
+            | CrnFormat::Etc1S => 8,
+            _ if self.name().is_some() => 16,
+            _ => 0,
+        }
+    }
+
+    /// Width of a block in texels, or 0 if this is not an actual format.
+    pub fn block_width(self) -> u32 {
+        if self.name().is_some() {
+            4
+        } else {
+            0
+        }
+    }
+
+    /// Height of a block in texels, or 0 if this is not an actual format.
+    pub fn block_height(self) -> u32 {
+        self.block_width()
+    }
+
+    /// Number of channels the format stores, counting luma and chroma as
+    /// three. DXT1's punch-through alpha is not counted.
+    pub fn channel_count(self) -> u32 {
+        match self {
+            CrnFormat::Dxt5A => 1,
+            CrnFormat::Dxt5xGxR | CrnFormat::DxNXy | CrnFormat::DxNYx => 2,
+            CrnFormat::Dxt1
+            | CrnFormat::Dxt5cCxY
+            | CrnFormat::Dxt5xGBR
+            | CrnFormat::Etc1
+            | CrnFormat::Etc2
+            | CrnFormat::Etc1S => 3,
+            CrnFormat::Dxt3
+            | CrnFormat::Dxt5
+            | CrnFormat::Dxt5Agbr
+            | CrnFormat::Etc2A
+            | CrnFormat::Etc2AS => 4,
+            _ => 0,
+        }
+    }
+
+    /// Whether the format stores a full alpha channel. DXT1's punch-through
+    /// alpha does not count, so this is false for `Dxt1`.
+    pub fn has_alpha(self) -> bool {
+        self.channel_count() == 4
+    }
+
+    /// Whether the format is a DXT5 variant with channels moved around, which
+    /// must be swizzled back after decoding.
+    pub fn is_swizzled(self) -> bool {
+        matches!(
+            self,
+            CrnFormat::Dxt5cCxY | CrnFormat::Dxt5xGxR | CrnFormat::Dxt5xGBR | CrnFormat::Dxt5Agbr
+        )
+    }
+}
+
+impl TryFrom<i32> for CrnFormat {
+    type Error = ParseFormatError;
+
+    /// Converts a `crn_format` value, accepting only actual formats.
+    fn try_from(value: i32) -> Result<CrnFormat, ParseFormatError> {
+        usize::try_from(value)
+            .ok()
+            .and_then(|index| FORMAT_NAMES.get(index))
+            .map(|&(format, _)| format)
+            .ok_or(ParseFormatError)
+    }
+}
+
+impl fmt::Display for CrnFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name().unwrap_or("invalid"))
+    }
+}
+
+impl FromStr for CrnFormat {
+    type Err = ParseFormatError;
+
+    fn from_str(s: &str) -> Result<CrnFormat, ParseFormatError> {
+        CrnFormat::from_name(s).ok_or(ParseFormatError)
+    }
+}
 
-#[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct LevelInfo {
-    pub struct_size: u32,
-    pub width: u32,
-    pub height: u32,
-    pub faces: u32,
-    pub blocks_x: u32,
-    pub blocks_y: u32,
-    pub bytes_per_block: u32,
-    pub format: CrnFormat,
+    pub(crate) struct_size: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) faces: u32,
+    pub(crate) blocks_x: u32,
+    pub(crate) blocks_y: u32,
+    pub(crate) bytes_per_block: u32,
+    pub(crate) format: CrnFormat,
+}
+
+impl LevelInfo {
+    /// Size of crunch's `crn_level_info`, checked when the info was read.
+    pub fn struct_size(&self) -> u32 {
+        self.struct_size
+    }
+
+    /// Width of the level in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the level in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of faces: 6 for a cubemap, otherwise 1.
+    pub fn faces(&self) -> u32 {
+        self.faces
+    }
+
+    /// Number of blocks in a row.
+    pub fn blocks_x(&self) -> u32 {
+        self.blocks_x
+    }
+
+    /// Number of rows of blocks.
+    pub fn blocks_y(&self) -> u32 {
+        self.blocks_y
+    }
+
+    /// Size of one block: 8 or 16 bytes.
+    pub fn bytes_per_block(&self) -> u32 {
+        self.bytes_per_block
+    }
+
+    /// Block format of the level.
+    pub fn format(&self) -> CrnFormat {
+        self.format
+    }
+
+    /// Iterates over the blocks of `decoded`, the output of decoding this
+    /// level, without any pitch arithmetic. Stops early if `decoded` is short.
+    pub fn blocks<'b>(&self, decoded: &'b [u8]) -> Blocks<'b> {
+        Blocks::new(self, decoded)
+    }
+
+    /// Size of the level, all faces included, with rows of blocks
+    /// `row_pitch` bytes apart. Checks the pitch as crunch would.
+    pub(crate) fn pitched_size(&self, row_pitch: u32) -> Result<usize, DecrunchError> {
+        let min_pitch = self.blocks_x * self.bytes_per_block;
+        if row_pitch < min_pitch {
+            return Err(DecrunchError::PitchTooSmall {
+                pitch: row_pitch,
+                min: min_pitch,
+            });
+        }
+        (row_pitch as usize)
+            .checked_mul(self.blocks_y as usize)
+            .and_then(|size| size.checked_mul(self.faces as usize))
+            .filter(|_| row_pitch.is_multiple_of(4))
+            .ok_or(DecrunchError::InvalidPitch { pitch: row_pitch })
+    }
 }
 
 impl Default for LevelInfo {
     fn default() -> LevelInfo {
         LevelInfo {
-            struct_size: mem::size_of::<LevelInfo>() as u32,
+            struct_size: mem::size_of::<sys::RawLevelInfo>() as u32,
             width: 0,
             height: 0,
             faces: 0,
@@ -124,24 +378,65 @@ impl Default for LevelInfo {
     }
 }
 
-#[repr(C)]
 #[derive(Debug, PartialEq)]
 pub struct TextureInfo {
-    pub struct_size: u32,
-    pub width: u32,
-    pub height: u32,
-    pub levels: u32,
-    pub faces: u32,
-    pub bytes_per_block: u32,
-    pub userdata0: u32,
-    pub userdata1: u32,
-    pub format: CrnFormat,
+    pub(crate) struct_size: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) levels: u32,
+    pub(crate) faces: u32,
+    pub(crate) bytes_per_block: u32,
+    pub(crate) userdata0: u32,
+    pub(crate) userdata1: u32,
+    pub(crate) format: CrnFormat,
+}
+
+impl TextureInfo {
+    /// Size of crunch's `crn_texture_info`, checked when the info was read.
+    pub fn struct_size(&self) -> u32 {
+        self.struct_size
+    }
+
+    /// Width of the top level in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the top level in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of mipmap levels.
+    pub fn levels(&self) -> u32 {
+        self.levels
+    }
+
+    /// Number of faces: 6 for a cubemap, otherwise 1.
+    pub fn faces(&self) -> u32 {
+        self.faces
+    }
+
+    /// Size of one block: 8 or 16 bytes.
+    pub fn bytes_per_block(&self) -> u32 {
+        self.bytes_per_block
+    }
+
+    /// The two user data words stored in the header.
+    pub fn userdata(&self) -> (u32, u32) {
+        (self.userdata0, self.userdata1)
+    }
+
+    /// Block format of the texture.
+    pub fn format(&self) -> CrnFormat {
+        self.format
+    }
 }
 
 impl Default for TextureInfo {
     fn default() -> TextureInfo {
         TextureInfo {
-            struct_size: mem::size_of::<TextureInfo>() as u32,
+            struct_size: mem::size_of::<sys::RawTextureInfo>() as u32,
             width: 0,
             height: 0,
             levels: 0,
@@ -154,49 +449,807 @@ impl Default for TextureInfo {
     }
 }
 
+/// Byte layout of a CRN file, as described by its header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadLayout {
+    /// Size of the header, including the level offset table.
+    pub header_size: u32,
+    /// Byte range of the Huffman tables.
+    pub tables: Range<usize>,
+    /// Total size of the file's data, header included.
+    pub data_size: u32,
+    /// Byte range of each mip level's compressed data.
+    pub levels: Vec<Range<usize>>,
+}
+
+impl PayloadLayout {
+    /// Size of the Huffman tables in bytes.
+    pub fn tables_size(&self) -> u32 {
+        (self.tables.end - self.tables.start) as u32
+    }
+
+    /// Size of all compressed level data in bytes.
+    pub fn levels_size(&self) -> u32 {
+        self.levels.iter().map(|r| (r.end - r.start) as u32).sum()
+    }
+}
+
 pub struct CrunchedData<'a> {
     pub buffer: &'a [u8],
     ctx: *const c_void,
+    cache: Vec<Option<Vec<u8>>>,
+    scratch: Option<&'a dyn ScratchAllocator>,
+    // Read once on construction for the accessors.
+    info: TextureInfo,
+    options: DecoderOptions,
 }
 
 impl<'a> CrunchedData<'a> {
-    pub fn new(buffer: &'a [u8]) -> Self {
-        CrunchedData {
+    /// Accepts anything byte-like, e.g. `&Vec<u8>`, `&Box<[u8]>` or
+    /// `&Cow<[u8]>`.
+    pub fn new<B: AsRef<[u8]> + ?Sized>(buffer: &'a B) -> Self {
+        Self::with_scratch(buffer.as_ref(), None)
+    }
+
+    /// Like `new`, but checks the signature, header size and format before
+    /// handing the buffer to crunch, and fails if its tables or palettes do
+    /// not decode.
+    pub fn try_new<B: AsRef<[u8]> + ?Sized>(buffer: &'a B) -> Result<Self, DecrunchError> {
+        let buffer = buffer.as_ref();
+        header::Header::validate(buffer)?;
+        let c_data = Self::new(buffer);
+        if c_data.ctx.is_null() {
+            return Err(DecrunchError::CorruptTables);
+        }
+        Ok(c_data)
+    }
+
+    /// Like `new`, but `decode` and the functions built on it apply
+    /// `options`.
+    pub fn with_options<B: AsRef<[u8]> + ?Sized>(buffer: &'a B, options: DecoderOptions) -> Self {
+        let mut c_data = Self::new(buffer);
+        c_data.options = options;
+        c_data
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Fails with `OutputTooLarge` if an output of `size` bytes, or of a size
+    /// that overflowed (`None`), would exceed the options' `max_output_size`.
+    pub(crate) fn check_output_size(&self, size: Option<usize>) -> Result<(), DecrunchError> {
+        let limit = self.options.max_output_size;
+        match size {
+            Some(size) if size <= limit => Ok(()),
+            size => Err(DecrunchError::OutputTooLarge {
+                size: size.unwrap_or(usize::MAX),
+                limit,
+            }),
+        }
+    }
+
+    /// The options set with `with_options`.
+    pub fn options(&self) -> &DecoderOptions {
+        &self.options
+    }
+
+    /// Like `new`, but crunch's internal allocations for this texture, both
+    /// now and while decoding, are served from `scratch`.
+    pub fn new_in(buffer: &'a [u8], scratch: &'a dyn ScratchAllocator) -> Self {
+        Self::with_scratch(buffer, Some(scratch))
+    }
+
+    /// Wraps a context created by `crnd_unpack_begin` for `buffer`, taking
+    /// ownership of it: it is freed with `crnd_unpack_end` on drop.
+    ///
+    /// # Safety
+    ///
+    /// `ctx` must be null or a live context created from `buffer`, and must
+    /// not be freed elsewhere. It must have been allocated through the
+    /// crate's memory callbacks, so it has to come from `as_raw_ctx` or
+    /// `sys::crnd_unpack_begin`, which installs them, and
+    /// `sys::crnd_set_memory_callbacks` must not have been called since. A
+    /// context created by crunch's default allocator is freed with the wrong
+    /// callbacks on drop.
+    ///
+    /// With the `simd` feature enabled, only contexts from `as_raw_ctx` are
+    /// accepted: `sys::crnd_unpack_begin` always uses the baseline decoder,
+    /// while this crate may use its AVX2 build.
+    pub unsafe fn from_raw_ctx(buffer: &'a [u8], ctx: *const c_void) -> Self {
+        let mut c_data = CrunchedData {
             buffer,
-            ctx: crunch::unpack_begin(buffer),
+            ctx,
+            cache: Vec::new(),
+            scratch: None,
+            info: TextureInfo::default(),
+            options: DecoderOptions::default(),
+        };
+        c_data.info = c_data.try_texture_info().unwrap_or_default();
+        c_data
+    }
+
+    /// Frees the decoder context now, reporting whether crunch accepted it,
+    /// rather than silently on drop.
+    pub fn close(mut self) -> Result<(), DecrunchError> {
+        let ctx = mem::replace(&mut self.ctx, ptr::null());
+        if ctx.is_null() || scratch::with(self.scratch, || crunch::unpack_end(ctx)) {
+            Ok(())
+        } else {
+            Err(DecrunchError::CloseFailed)
         }
     }
 
+    /// The underlying `crnd_unpack_context`, null if the tables failed to
+    /// decode, for calling crunch functions this crate does not wrap. It
+    /// stays owned by this value.
+    pub fn as_raw_ctx(&self) -> *const c_void {
+        self.ctx
+    }
+
+    fn with_scratch(buffer: &'a [u8], scratch: Option<&'a dyn ScratchAllocator>) -> Self {
+        let ctx = scratch::with(scratch, || crunch::unpack_begin(buffer));
+        let mut c_data = unsafe { Self::from_raw_ctx(buffer, ctx) };
+        c_data.scratch = scratch;
+        c_data
+    }
+
+    /// Decodes whatever levels of a possibly truncated file have complete
+    /// data, and describes what was lost. Returns `None` if not even one level
+    /// can be recovered.
+    pub fn decode_truncated(buffer: &[u8]) -> Option<RecoveredTexture> {
+        recovery::decode_truncated(buffer)
+    }
+
     /// Retrieves mipmap level specific information from the CRN data.
     pub fn level_info(&self, level: u32) -> LevelInfo {
-        crunch::get_level_info(self, level)
+        crunch::get_level_info(self.buffer, level).unwrap_or_default()
     }
 
     /// Retrieves texture information from the CRN data.
     pub fn texture_info(&self) -> TextureInfo {
-        crunch::get_texture_info(self)
+        crunch::get_texture_info(self.buffer).unwrap_or_default()
+    }
+
+    /// Like `texture_info`, but reports why the header cannot be read.
+    pub fn try_texture_info(&self) -> Result<TextureInfo, DecrunchError> {
+        // Checked here as well so an unknown format is reported as such.
+        header::Header::validate(self.buffer)?;
+        crunch::get_texture_info(self.buffer).ok_or(DecrunchError::InvalidHeader)
+    }
+
+    /// Like `level_info`, but reports why the level cannot be described.
+    pub fn try_level_info(&self, level: u32) -> Result<LevelInfo, DecrunchError> {
+        let levels = self.try_texture_info()?.levels;
+        if level >= levels {
+            return Err(DecrunchError::LevelOutOfRange { level, levels });
+        }
+        crunch::get_level_info(self.buffer, level).ok_or(DecrunchError::InvalidHeader)
+    }
+
+    /// Iterates over the `LevelInfo` of every mipmap level, largest first.
+    /// Yields nothing if the header is invalid.
+    pub fn levels(&self) -> Levels<'a> {
+        Levels::new(self)
+    }
+
+    /// Indices of the texture's faces: six for cubemaps, otherwise one.
+    /// Empty if the header is invalid.
+    pub fn faces(&self) -> Range<u32> {
+        0..self.info.faces
+    }
+
+    /// Width of the top level in texels, or 0 if the header is invalid.
+    pub fn width(&self) -> u32 {
+        self.info.width
+    }
+
+    /// Height of the top level in texels, or 0 if the header is invalid.
+    pub fn height(&self) -> u32 {
+        self.info.height
+    }
+
+    pub fn level_count(&self) -> u32 {
+        self.info.levels
+    }
+
+    pub fn face_count(&self) -> u32 {
+        self.info.faces
+    }
+
+    /// The texture's format, or `CrnFormat::Invalid` if the header is invalid.
+    pub fn format(&self) -> CrnFormat {
+        self.info.format
+    }
+
+    /// Checks `level` against the texture's level count. The index is only
+    /// meaningful for this texture.
+    pub fn level(&self, level: u32) -> Result<LevelIndex, DecrunchError> {
+        let levels = self.info.levels;
+        if level >= levels {
+            return Err(DecrunchError::LevelOutOfRange { level, levels });
+        }
+        Ok(LevelIndex(level))
     }
 
-    /// Transcodes the specified mipmap level to a destination buffer.
+    /// Checks `face` against the texture's face count. The index is only
+    /// meaningful for this texture.
+    pub fn face(&self, face: u32) -> Result<FaceIndex, DecrunchError> {
+        let faces = self.info.faces;
+        if face >= faces {
+            return Err(DecrunchError::FaceOutOfRange { face, faces });
+        }
+        Ok(FaceIndex(face))
+    }
+
+    /// Retrieves information about a level already checked with `level`.
+    pub fn level_info_at(&self, level: LevelIndex) -> LevelInfo {
+        self.level_info(level.0)
+    }
+
+    /// `decode_level` for a level already checked with `level`.
+    pub fn decode_level_at(&self, level: LevelIndex) -> Option<Vec<u8>> {
+        self.decode_level(level.0)
+    }
+
+    /// `decode` for a level already checked with `level`.
+    pub fn decode_at(&self, level: LevelIndex) -> Result<DecodedLevel, DecrunchError> {
+        self.decode(level.0)
+    }
+
+    /// `decode_level_into` for a level already checked with `level`.
+    pub fn decode_level_into_at(
+        &self,
+        level: LevelIndex,
+        dst: &mut [u8],
+    ) -> Result<(), DecrunchError> {
+        self.decode_level_into(level.0, dst)
+    }
+
+    /// `decode_face` for indices already checked with `level` and `face`.
+    pub fn decode_face_at(
+        &self,
+        level: LevelIndex,
+        face: FaceIndex,
+    ) -> Result<DecodedLevel, DecrunchError> {
+        self.decode_face(level.0, face.0)
+    }
+
+    /// Decoded size in bytes of a level, all faces included, with the tight
+    /// row pitch. Returns `None` if the level does not exist or the size
+    /// overflows `usize`.
+    pub fn expected_level_size(&self, level: u32) -> Option<usize> {
+        let info = self.try_level_info(level).ok()?;
+        (info.blocks_x as usize)
+            .checked_mul(info.blocks_y as usize)?
+            .checked_mul(info.bytes_per_block as usize)?
+            .checked_mul(info.faces as usize)
+    }
+
+    /// Decoded size in bytes of every level and face together. Returns `None`
+    /// if the header is invalid or the size overflows `usize`.
+    pub fn total_decoded_size(&self) -> Option<usize> {
+        self.try_texture_info().ok()?;
+        (0..self.info.levels).try_fold(0usize, |total, level| {
+            total.checked_add(self.expected_level_size(level)?)
+        })
+    }
+
+    /// Like `try_level_info`, but for a single face, so `faces` is 1 and the
+    /// block counts cover only that face.
+    pub fn face_level_info(&self, face: u32, level: u32) -> Result<LevelInfo, DecrunchError> {
+        self.face(face)?;
+        let info = self.try_level_info(level)?;
+        Ok(LevelInfo { faces: 1, ..info })
+    }
+
+    /// The full parsed header. Only the signature and header size are
+    /// checked, so this also works on files crunch rejects.
+    pub fn header(&self) -> Result<CrnHeader, DecrunchError> {
+        CrnHeader::parse(self.buffer).ok_or(DecrunchError::InvalidHeader)
+    }
+
+    /// Reads the byte layout of the file from its header, so it can be sliced
+    /// or streamed without parsing the header again. Returns `None` if the
+    /// header is invalid or a level lies outside the data.
+    pub fn payload_layout(&self) -> Option<PayloadLayout> {
+        let h = header::Header::parse(self.buffer)?;
+        let levels = (0..h.levels)
+            .map(|level| {
+                let start = h.level_ofs[level as usize];
+                let end = start.checked_add(h.level_size(level)?)?;
+                if end > h.data_size {
+                    return None;
+                }
+                Some(start as usize..end as usize)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(PayloadLayout {
+            header_size: h.header_size,
+            tables: h.tables_ofs as usize..(h.tables_ofs + h.tables_size) as usize,
+            data_size: h.data_size,
+            levels,
+        })
+    }
+
+    /// Byte range of a mip level's compressed data within `buffer`.
+    pub fn level_range(&self, level: u32) -> Option<Range<usize>> {
+        self.payload_layout()?.levels.get(level as usize).cloned()
+    }
+
+    /// Lists file features that the bundled decoder may not support. Returns
+    /// `None` if the header cannot be parsed.
+    ///
+    /// CRN headers carry no writer version, so this inspects the format,
+    /// flags and header size instead. Compare against `DECODER_VERSION` for
+    /// the decoder revision compiled into the crate.
+    pub fn compat_warnings(&self) -> Option<Vec<CompatWarning>> {
+        header::Header::parse(self.buffer).map(|h| compat::check(&h))
+    }
+
+    /// Validates every header field and data segment of the file and reports
+    /// each problem with its byte offset and the expected and actual values,
+    /// for working out why a file fails to decode.
+    pub fn forensic_report(&self) -> ForensicReport {
+        forensics::inspect(self.buffer)
+    }
+
+    /// Collects non-fatal anomalies in the file, such as unknown header
+    /// flags, trailing bytes or levels failing `check_level`, so they can be
+    /// logged without failing the decode.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        diagnostics::collect(self)
+    }
+
+    /// Transcodes the specified mipmap level to a destination buffer. The
+    /// faces of a cubemap follow one another, in the face order and
+    /// orientation of the options' `CubeConvention`, Direct3D's by default.
     pub fn decode_level(&self, level: u32) -> Option<Vec<u8>> {
-        let info = self.level_info(level);
-        let mut dst: Vec<u8> =
-            vec![0; (info.blocks_x * info.blocks_y * info.bytes_per_block) as usize];
-        if !crunch::unpack_level(
-            self.ctx,
-            &mut dst,
-            info.blocks_x * info.bytes_per_block,
-            level,
-        ) {
+        self.try_decode_level(level).ok()
+    }
+
+    /// Like `decode_level`, but reports why the level cannot be decoded.
+    pub fn try_decode_level(&self, level: u32) -> Result<Vec<u8>, DecrunchError> {
+        let info = self.try_level_info(level)?;
+        self.decode_level_with_pitch(level, info.blocks_x * info.bytes_per_block)
+    }
+
+    /// Like `try_decode_level`, but returns the level's dimensions, row pitch
+    /// and format along with its data, laid out as set by `with_options`.
+    pub fn decode(&self, level: u32) -> Result<DecodedLevel, DecrunchError> {
+        let mut decoded = self.decode_aligned(level, self.options.row_alignment)?;
+        if self.options.flip_vertical {
+            let info = self.try_level_info(level)?;
+            if !block::flip_level(&info, &mut decoded.data, decoded.row_pitch as usize) {
+                return Err(DecrunchError::FlipUnsupported(info.format));
+            }
+        }
+        Ok(decoded)
+    }
+
+    /// Like `try_decode_level`, but with the level mirrored vertically, as
+    /// OpenGL expects. Rows of blocks are reversed and each block is flipped,
+    /// so the result is exact when the height is a multiple of 4. ETC formats
+    /// cannot be flipped and fail with `FlipUnsupported`.
+    pub fn decode_level_flipped(&self, level: u32) -> Result<Vec<u8>, DecrunchError> {
+        let info = self.try_level_info(level)?;
+        let mut data = self.try_decode_level(level)?;
+        let pitch = (info.blocks_x * info.bytes_per_block) as usize;
+        if !block::flip_level(&info, &mut data, pitch) {
+            return Err(DecrunchError::FlipUnsupported(info.format));
+        }
+        Ok(data)
+    }
+
+    /// Like `decode`, but returns only one face of a cubemap level. crunch
+    /// unpacks the faces of a level together, so this costs as much as
+    /// decoding the whole level.
+    pub fn decode_face(&self, level: u32, face: u32) -> Result<DecodedLevel, DecrunchError> {
+        self.face(face)?;
+        let mut decoded = self.decode(level)?;
+        let face_size = decoded.row_pitch as usize * decoded.blocks_y as usize;
+        let start = face as usize * face_size;
+        decoded.data.truncate(start + face_size);
+        decoded.data.drain(..start);
+        decoded.faces = 1;
+        Ok(decoded)
+    }
+
+    /// Transcodes a contiguous range of levels, such as `2..levels` to skip
+    /// the largest mips when streaming, with the same decoder context.
+    pub fn decode_levels(&self, levels: Range<u32>) -> Result<Vec<DecodedLevel>, DecrunchError> {
+        let count = self.try_texture_info()?.levels;
+        if levels.end > count {
+            return Err(DecrunchError::LevelOutOfRange {
+                level: levels.end - 1,
+                levels: count,
+            });
+        }
+        levels.map(|level| self.decode(level)).collect()
+    }
+
+    /// Transcodes every level independently, so levels with damaged data do
+    /// not prevent the others from being returned. Empty if the header is
+    /// invalid.
+    pub fn decode_levels_lenient(&self) -> Vec<Result<DecodedLevel, DecrunchError>> {
+        (0..self.info.levels)
+            .map(|level| self.decode(level))
+            .collect()
+    }
+
+    /// Transcodes every level of the texture, along with the dimensions and
+    /// row pitch of each, failing on the first level that cannot be decoded.
+    pub fn decode_all_levels(&self) -> Result<DecodedTexture, DecrunchError> {
+        self.decode_all_levels_with_progress(|_| {})
+    }
+
+    /// Like `decode_all_levels`, but calls `progress` after each level, for
+    /// driving a progress bar. crunch decodes a level in one call, so there
+    /// is no finer granularity.
+    pub fn decode_all_levels_with_progress<F: FnMut(Progress)>(
+        &self,
+        mut progress: F,
+    ) -> Result<DecodedTexture, DecrunchError> {
+        let info = self.try_texture_info()?;
+        let mut levels = Vec::with_capacity(info.levels as usize);
+        let mut bytes_done = 0;
+        for level in 0..info.levels {
+            let decoded = self.decode(level)?;
+            bytes_done += decoded.data.len();
+            levels.push(decoded);
+            progress(Progress {
+                levels_done: level + 1,
+                levels_total: info.levels,
+                bytes_done,
+            });
+        }
+        Ok(DecodedTexture {
+            width: info.width,
+            height: info.height,
+            faces: info.faces,
+            format: info.format,
+            levels,
+        })
+    }
+
+    /// Like `decode_all_levels`, but packs every face of every level into one
+    /// buffer ordered by `layout`, with the byte range of each.
+    pub fn decode_all_packed(&self, layout: TextureLayout) -> Result<PackedTexture, DecrunchError> {
+        Ok(self.decode_all_levels()?.pack(layout))
+    }
+
+    /// Transcodes the specified mipmap level into `dst`, which must hold at
+    /// least `blocks_x * blocks_y * bytes_per_block * faces` bytes, so buffers
+    /// can be reused across levels and textures. Bytes past the level are
+    /// left untouched.
+    pub fn decode_level_into(&self, level: u32, dst: &mut [u8]) -> Result<(), DecrunchError> {
+        let info = self.try_level_info(level)?;
+        self.decode_level_into_with_pitch(level, dst, info.blocks_x * info.bytes_per_block)
+    }
+
+    /// Like `decode_level`, but starts each row of blocks `row_pitch` bytes
+    /// after the previous one, leaving the padding zeroed. The pitch must be
+    /// a multiple of 4.
+    pub fn decode_level_with_pitch(
+        &self,
+        level: u32,
+        row_pitch: u32,
+    ) -> Result<Vec<u8>, DecrunchError> {
+        let info = self.try_level_info(level)?;
+        let size = info.pitched_size(row_pitch)?;
+        self.check_output_size(Some(size))?;
+        let mut dst = vec![0; size];
+        self.decode_level_into_with_pitch(level, &mut dst, row_pitch)?;
+        Ok(dst)
+    }
+
+    /// The tight row pitch of a level rounded up to a multiple of
+    /// `alignment`, such as the 256 bytes D3D12 requires for texture uploads.
+    /// An alignment of 0 is treated as 1. crunch writes rows at multiples of
+    /// 4 bytes, so the pitch is also kept a multiple of 4.
+    pub fn aligned_row_pitch(&self, level: u32, alignment: u32) -> Result<u32, DecrunchError> {
+        let info = self.try_level_info(level)?;
+        let pitch = info.blocks_x * info.bytes_per_block;
+        let too_large = DecrunchError::OutOfLimits {
+            field: "row alignment",
+            value: alignment,
+        };
+        // Least common multiple with 4.
+        let alignment = alignment.max(1);
+        let alignment = match alignment % 4 {
+            0 => alignment,
+            2 => alignment.checked_mul(2).ok_or(too_large.clone())?,
+            _ => alignment.checked_mul(4).ok_or(too_large.clone())?,
+        };
+        pitch
+            .div_ceil(alignment)
+            .checked_mul(alignment)
+            .ok_or(too_large)
+    }
+
+    /// Like `decode`, but pads each row of blocks to a multiple of
+    /// `alignment` bytes. The resulting pitch is in the level's `row_pitch`.
+    pub fn decode_aligned(
+        &self,
+        level: u32,
+        alignment: u32,
+    ) -> Result<DecodedLevel, DecrunchError> {
+        let info = self.try_level_info(level)?;
+        let row_pitch = self.aligned_row_pitch(level, alignment)?;
+        let data = self.decode_level_with_pitch(level, row_pitch)?;
+        Ok(DecodedLevel {
+            row_pitch,
+            ..DecodedLevel::new(&info, data)
+        })
+    }
+
+    /// Like `decode_level_into`, but starts each row of blocks `row_pitch`
+    /// bytes after the previous one, so the level can be written straight
+    /// into a padded staging buffer. `dst` must hold `row_pitch * blocks_y`
+    /// bytes per face, with the faces one after another; padding between rows
+    /// is left untouched. The pitch must be a multiple of 4.
+    pub fn decode_level_into_with_pitch(
+        &self,
+        level: u32,
+        dst: &mut [u8],
+        row_pitch: u32,
+    ) -> Result<(), DecrunchError> {
+        if self.is_cancelled() {
+            return Err(DecrunchError::Cancelled);
+        }
+        let info = self.try_level_info(level)?;
+        let needed = info.pitched_size(row_pitch)?;
+        if dst.len() < needed {
+            return Err(DecrunchError::BufferTooSmall {
+                needed,
+                actual: dst.len(),
+            });
+        }
+        if self.ctx.is_null() {
+            return Err(DecrunchError::CorruptTables);
+        }
+        if !scratch::with(self.scratch, || {
+            crunch::unpack_level(self.ctx, &mut dst[..needed], info.faces, row_pitch, level)
+        }) {
+            return Err(DecrunchError::DecodeFailed { level });
+        }
+        cube::orient_level(&info, dst, row_pitch as usize, self.options.cube_convention)
+    }
+
+    /// Transcodes the specified mipmap level to blocks of a format known at
+    /// compile time, e.g. `decode_typed::<format::Dxt5>(0)`. Returns `None`
+    /// if the texture has a different format or the level fails to decode.
+    pub fn decode_typed<F: format::Format>(&self, level: u32) -> Option<Vec<F::Block>> {
+        if self.texture_info().format != F::FORMAT {
             return None;
         }
-        Some(dst)
+        let decoded = self.decode_level(level)?;
+        Some(decoded.chunks_exact(F::BLOCK_SIZE).map(F::block).collect())
+    }
+
+    /// Decodes the specified mipmap level into the texture's cache, unless it
+    /// is already there, and borrows the cached data.
+    pub fn cache_level(&mut self, level: u32) -> Option<&[u8]> {
+        self.try_cache_level(level).ok()
+    }
+
+    /// Like `cache_level`, but reports why the level cannot be decoded.
+    pub fn try_cache_level(&mut self, level: u32) -> Result<&[u8], DecrunchError> {
+        let index = level as usize;
+        if self.cached_level(level).is_none() {
+            let decoded = self.try_decode_level(level)?;
+            if self.cache.len() <= index {
+                self.cache.resize(index + 1, None);
+            }
+            self.cache[index] = Some(decoded);
+        }
+        Ok(self.cache[index].as_ref().unwrap())
+    }
+
+    /// Borrows a level previously decoded with `cache_level`.
+    pub fn cached_level(&self, level: u32) -> Option<&[u8]> {
+        match self.cache.get(level as usize) {
+            Some(Some(data)) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Drops a cached level. Returns whether it was cached.
+    pub fn evict_level(&mut self, level: u32) -> bool {
+        match self.cache.get_mut(level as usize) {
+            Some(entry) => entry.take().is_some(),
+            None => false,
+        }
+    }
+
+    /// Drops all cached levels.
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Fraction of the level's texels whose alpha is above `threshold`,
+    /// counting every face of a cubemap. Returns `None` for formats without
+    /// an alpha channel or if the level fails to decode.
+    pub fn alpha_coverage(&self, level: u32, threshold: u8) -> Option<f32> {
+        let info = self.level_info(level);
+        analysis::alpha_coverage(&info, &self.decode_level(level)?, threshold)
+    }
+
+    /// `alpha_coverage` for every level, to spot alpha-tested textures whose
+    /// coverage collapses in the smaller mips.
+    pub fn alpha_coverage_per_level(&self, threshold: u8) -> Option<Vec<f32>> {
+        (0..self.texture_info().levels)
+            .map(|level| self.alpha_coverage(level, threshold))
+            .collect()
+    }
+
+    /// Computes per-channel histograms of the specified mipmap level, all
+    /// faces included, from which average and dominant colors can be read.
+    /// A small mip gives a good estimate at a fraction of the cost. Returns
+    /// `None` for formats without a software decoder (currently the ETC2
+    /// formats).
+    pub fn histogram(&self, level: u32) -> Option<Histogram> {
+        let info = self.level_info(level);
+        analysis::histogram(&info, &self.decode_level(level)?)
+    }
+
+    /// Estimates the texture's average color from its endpoint and selector
+    /// palettes without decoding any level, e.g. for placeholder colors in
+    /// thumbnailers. Less accurate than `histogram`. Returns `None` for
+    /// formats other than DXT1/3/5.
+    pub fn palette_average_color(&self) -> Option<[u8; 4]> {
+        let palette = |which| crunch::get_palette(self.ctx, which);
+        analysis::palette_average_color(
+            self.texture_info().format,
+            &palette(crunch::Palette::ColorEndpoints)?,
+            &palette(crunch::Palette::ColorSelectors)?,
+            &palette(crunch::Palette::AlphaEndpoints)?,
+        )
+    }
+
+    /// Decodes the specified mipmap level to interleaved RGBA8 pixels, row by
+    /// row with no padding, in the channel order and pixel format set in the
+    /// options. The faces of a cubemap follow one another, as in
+    /// `decode_level`. DXT1 punch-through texels get an alpha of 0. Returns
+    /// `None` for formats without a software decoder (currently the ETC2
+    /// formats).
+    pub fn decode_level_to_rgba(&self, level: u32) -> Option<Vec<u8>> {
+        let info = self.level_info(level);
+        let size = pixels::output_size(&info, self.options.pixel_format.bytes_per_pixel());
+        self.check_output_size(size).ok()?;
+        pixels::transcode(&info, &self.decode_level(level)?, &self.options)
+    }
+
+    /// Decodes the specified mipmap level to separate R, G, B and A planes
+    /// instead of interleaved pixels, with the faces of a cubemap stacked.
+    /// Returns `None` for formats without a software decoder (currently the
+    /// ETC2 formats).
+    pub fn decode_to_planes(&self, level: u32) -> Option<Planes> {
+        let info = self.level_info(level);
+        self.check_output_size(pixels::output_size(&info, 4)).ok()?;
+        let cancel = self.options.cancel.as_ref();
+        pixels::planes(&info, &self.decode_level(level)?, cancel)
+    }
+
+    /// Decodes the specified mipmap level to pixels packed into `u32`s, whose
+    /// values hold the channels in `order` from the most significant byte
+    /// down (`Argb` gives `0xAARRGGBB`). `endian` sets the byte order of the
+    /// values in memory. The faces of a cubemap follow one another. Returns
+    /// `None` for formats without a software decoder (currently the ETC2
+    /// formats).
+    pub fn decode_level_packed(
+        &self,
+        level: u32,
+        order: ChannelOrder,
+        endian: Endian,
+    ) -> Option<Vec<u32>> {
+        let info = self.level_info(level);
+        self.check_output_size(pixels::output_size(&info, 4)).ok()?;
+        let cancel = self.options.cancel.as_ref();
+        pixels::packed(&info, &self.decode_level(level)?, order, endian, cancel)
+    }
+
+    /// Decodes the specified mipmap level to interleaved RGBA32F pixels in
+    /// linear light, applying the sRGB EOTF to the color channels unless the
+    /// options tag the texture as linear; alpha is scaled to [0, 1]
+    /// unchanged. The faces of a cubemap follow one another. Returns `None`
+    /// for formats without a software decoder (currently the ETC2 formats).
+    pub fn decode_level_to_linear(&self, level: u32) -> Option<Vec<f32>> {
+        let info = self.level_info(level);
+        self.check_output_size(pixels::output_size(&info, 16))
+            .ok()?;
+        let decoded = self.decode_level(level)?;
+        let cancel = self.options.cancel.as_ref();
+        pixels::linear(&info, &decoded, self.options.color_space, cancel)
+    }
+
+    /// Decodes every mipmap level to interleaved pixels as
+    /// `decode_level_to_rgba` does, converting one level on a second thread
+    /// while the next is unpacked. Returns `None` if a level fails to decode
+    /// or for formats without a software decoder (currently the ETC2
+    /// formats).
+    ///
+    /// The overlap is per level, not per row: crunch unpacks a whole level in
+    /// one call and offers no way to read rows before it returns. Most of the
+    /// time goes into level 0, so expect a modest gain on mipmapped textures
+    /// and none on a texture with a single level.
+    pub fn decode_levels_to_rgba(&self) -> Option<Vec<Vec<u8>>> {
+        pixels::decode_levels_to_rgba(self)
+    }
+
+    /// Cuts a tile of blocks out of the specified mipmap level. The level is
+    /// decoded into the cache, as by `cache_level`, so later tiles of the same
+    /// level are only copied. Fails with `InvalidTile` if the tile is not
+    /// block aligned or extends past the level.
+    pub fn decode_tile(&mut self, level: u32, tile: Tile) -> Result<Vec<u8>, DecrunchError> {
+        self.decode_tiles(level, &[tile])
+            .map(|mut tiles| tiles.remove(0))
+    }
+
+    /// Like `decode_tile`, but cuts several tiles from the same level.
+    pub fn decode_tiles(
+        &mut self,
+        level: u32,
+        tiles: &[Tile],
+    ) -> Result<Vec<Vec<u8>>, DecrunchError> {
+        tile::decode_tiles(self, level, tiles)
+    }
+
+    /// Cross-checks the level's `LevelInfo` against values recomputed from
+    /// the header and against the size of the level's compressed data.
+    pub fn check_level(&self, level: u32) -> Result<(), Inconsistency> {
+        strict::check_level(self, level)
+    }
+
+    /// Like `decode_level`, but fails on any inconsistency reported by
+    /// `check_level` instead of decoding it into garbage.
+    pub fn decode_level_strict(&self, level: u32) -> Result<Vec<u8>, Inconsistency> {
+        self.check_level(level)?;
+        self.decode_level(level).ok_or(Inconsistency::DecodeFailed)
+    }
+}
+
+/// Creates a new decoder context for the same buffer, so the clone can decode
+/// on another thread. Options and cached levels are copied.
+impl Clone for CrunchedData<'_> {
+    fn clone(&self) -> Self {
+        let mut c_data = Self::with_scratch(self.buffer, self.scratch);
+        c_data.cache = self.cache.clone();
+        c_data.options = self.options.clone();
+        c_data
+    }
+}
+
+/// Validates the buffer as `CrunchedData::try_new` does.
+impl<'a> TryFrom<&'a [u8]> for CrunchedData<'a> {
+    type Error = DecrunchError;
+
+    fn try_from(buffer: &'a [u8]) -> Result<Self, DecrunchError> {
+        Self::try_new(buffer)
+    }
+}
+
+impl fmt::Debug for CrunchedData<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CrunchedData")
+            .field("width", &self.info.width)
+            .field("height", &self.info.height)
+            .field("levels", &self.info.levels)
+            .field("faces", &self.info.faces)
+            .field("format", &self.info.format)
+            .field("size", &self.buffer.len())
+            .finish()
     }
 }
 
 impl Drop for CrunchedData<'_> {
     fn drop(&mut self) {
-        crunch::unpack_end(self.ctx);
+        if !self.ctx.is_null() {
+            scratch::with(self.scratch, || crunch::unpack_end(self.ctx));
+        }
     }
 }
 
@@ -0,0 +1,169 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! A directory of crunched textures, decoded on demand.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crunch;
+use header::Header;
+use reader;
+use CrunchedDataOwned;
+use DecrunchError;
+use TextureInfo;
+
+struct Entry {
+    path: PathBuf,
+    info: TextureInfo,
+    texture: Option<CrunchedDataOwned>,
+}
+
+/// The crunched textures of a directory, indexed by file name.
+///
+/// Only the headers are read up front. A texture is loaded, and its tables
+/// and palettes unpacked, the first time it is used; it then stays loaded
+/// with its own level cache until `unload` is called.
+pub struct TextureLibrary {
+    entries: BTreeMap<String, Entry>,
+    errors: Vec<(PathBuf, io::Error)>,
+}
+
+fn invalid_data(err: DecrunchError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+impl TextureLibrary {
+    /// Indexes every file in `dir` that starts with a valid CRN header.
+    /// Subdirectories and other files are skipped. Files that cannot be read
+    /// are left out and listed by `errors`; only a failure to list `dir`
+    /// itself is returned.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<TextureLibrary> {
+        let dir = dir.as_ref();
+        let mut library = TextureLibrary {
+            entries: BTreeMap::new(),
+            errors: Vec::new(),
+        };
+        for dir_entry in fs::read_dir(dir)? {
+            let path = match dir_entry {
+                Ok(dir_entry) => dir_entry.path(),
+                Err(err) => {
+                    library.errors.push((dir.to_path_buf(), err));
+                    continue;
+                }
+            };
+            if !path.is_file() {
+                continue;
+            }
+            let header = File::open(&path).and_then(|mut file| reader::read_header(&mut file));
+            let mut header = match header {
+                Ok(header) => header,
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => continue,
+                Err(err) => {
+                    library.errors.push((path, err));
+                    continue;
+                }
+            };
+            if Header::parse_prefix(&header).is_none() {
+                continue;
+            }
+            reader::segment(&mut header);
+            let name = match path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let info = crunch::get_texture_info(&header).unwrap_or_default();
+            library.entries.insert(
+                name,
+                Entry {
+                    path,
+                    info,
+                    texture: None,
+                },
+            );
+        }
+        Ok(library)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Names of the indexed textures, in sorted order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|name| name.as_str())
+    }
+
+    /// Files of the directory that could not be read when it was opened.
+    pub fn errors(&self) -> &[(PathBuf, io::Error)] {
+        &self.errors
+    }
+
+    pub fn texture_info(&self, name: &str) -> Option<&TextureInfo> {
+        self.entries.get(name).map(|entry| &entry.info)
+    }
+
+    /// The named texture, loading it on first use. Its cache holds the
+    /// levels decoded through `cache_level`.
+    pub fn texture(&mut self, name: &str) -> io::Result<&mut CrunchedDataOwned> {
+        let entry = self.entries.get_mut(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no texture named {}", name),
+            )
+        })?;
+        if entry.texture.is_none() {
+            let texture = CrunchedDataOwned::try_new(fs::read(&entry.path)?);
+            entry.texture = Some(texture.map_err(invalid_data)?);
+        }
+        Ok(entry.texture.as_mut().unwrap())
+    }
+
+    /// Transcodes a mipmap level of the named texture.
+    pub fn decode_level(&mut self, name: &str, level: u32) -> io::Result<Vec<u8>> {
+        self.texture(name)?
+            .data()
+            .try_decode_level(level)
+            .map_err(invalid_data)
+    }
+
+    /// Decodes a level into the named texture's cache, unless it is already
+    /// there, and borrows the cached data.
+    pub fn cache_level(&mut self, name: &str, level: u32) -> io::Result<&[u8]> {
+        self.texture(name)?
+            .try_cache_level(level)
+            .map_err(invalid_data)
+    }
+
+    /// Drops the named texture's decoder context and cached levels. Returns
+    /// whether it was loaded.
+    pub fn unload(&mut self, name: &str) -> bool {
+        match self.entries.get_mut(name) {
+            Some(entry) => entry.texture.take().is_some(),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! LZ4 block decompression, as used by Unity asset bundles.
+
+/// Decompresses an LZ4 block that expands to exactly `size` bytes. Returns
+/// `None` if the block is malformed.
+pub(crate) fn decompress(src: &[u8], size: usize) -> Option<Vec<u8>> {
+    fn length(src: &[u8], pos: &mut usize, mut len: usize) -> Option<usize> {
+        if len == 15 {
+            loop {
+                let b = *src.get(*pos)?;
+                *pos += 1;
+                len += b as usize;
+                if b != 255 {
+                    break;
+                }
+            }
+        }
+        Some(len)
+    }
+
+    // A byte of input expands to at most 255 bytes of output, which bounds
+    // the allocation when `size` comes from a corrupt header.
+    let mut out = Vec::with_capacity(size.min(src.len().saturating_mul(255)));
+    let mut pos = 0;
+    while pos < src.len() {
+        let token = src[pos];
+        pos += 1;
+
+        let literals = length(src, &mut pos, (token >> 4) as usize)?;
+        out.extend_from_slice(src.get(pos..pos + literals)?);
+        pos += literals;
+        if pos == src.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([*src.get(pos)?, *src.get(pos + 1)?]) as usize;
+        pos += 2;
+        let len = length(src, &mut pos, (token & 15) as usize)? + 4;
+        if offset == 0 || offset > out.len() || out.len() + len > size {
+            return None;
+        }
+        // Matches may overlap the bytes they produce, so copy one at a time.
+        let start = out.len() - offset;
+        for i in start..start + len {
+            let b = out[i];
+            out.push(b);
+        }
+    }
+    if out.len() == size {
+        Some(out)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Options applied by `CrunchedData::decode` and the functions built on it.
+
+use CancelToken;
+use ChannelOrder;
+use ColorSpace;
+use CubeConvention;
+use PixelFormat;
+
+/// How `CrunchedData::decode`, `decode_levels` and `decode_all_levels` lay
+/// out their output. The defaults match `decode_level`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecoderOptions {
+    pub(crate) row_alignment: u32,
+    pub(crate) flip_vertical: bool,
+    pub(crate) max_output_size: usize,
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) channel_order: ChannelOrder,
+    pub(crate) pixel_format: PixelFormat,
+    pub(crate) reconstruct_z: bool,
+    pub(crate) unswizzle: bool,
+    pub(crate) premultiply_alpha: bool,
+    pub(crate) color_space: ColorSpace,
+    pub(crate) output_color_space: Option<ColorSpace>,
+    pub(crate) dither: bool,
+    pub(crate) cube_convention: CubeConvention,
+}
+
+/// Default for `DecoderOptions::with_max_output_size`: enough for the largest
+/// level crunch can produce, 4096x4096 DXT5, with room to spare.
+pub const DEFAULT_MAX_OUTPUT_SIZE: usize = 256 << 20;
+
+impl Default for DecoderOptions {
+    fn default() -> DecoderOptions {
+        DecoderOptions {
+            row_alignment: 1,
+            flip_vertical: false,
+            max_output_size: DEFAULT_MAX_OUTPUT_SIZE,
+            cancel: None,
+            channel_order: ChannelOrder::Rgba,
+            pixel_format: PixelFormat::Rgba8,
+            reconstruct_z: false,
+            unswizzle: false,
+            premultiply_alpha: false,
+            color_space: ColorSpace::Srgb,
+            output_color_space: None,
+            dither: false,
+            cube_convention: CubeConvention::Direct3D,
+        }
+    }
+}
+
+impl DecoderOptions {
+    pub fn new() -> DecoderOptions {
+        DecoderOptions::default()
+    }
+
+    /// Pads each row of blocks to a multiple of `alignment` bytes, as
+    /// `CrunchedData::decode_aligned` does.
+    pub fn with_row_alignment(mut self, alignment: u32) -> DecoderOptions {
+        self.row_alignment = alignment.max(1);
+        self
+    }
+
+    /// Mirrors levels vertically, as `CrunchedData::decode_level_flipped`
+    /// does.
+    pub fn with_flip_vertical(mut self, flip: bool) -> DecoderOptions {
+        self.flip_vertical = flip;
+        self
+    }
+
+    /// Fails any decode that would allocate more than `size` bytes for one
+    /// level with `OutputTooLarge`, so a header declaring huge dimensions
+    /// cannot exhaust memory. Applies to every decode function of
+    /// `CrunchedData`, not only `decode`: the blocks, tiles and converted
+    /// pixels are each checked before they are allocated. The pixel
+    /// converters, which return `Option`, give `None` instead.
+    pub fn with_max_output_size(mut self, size: usize) -> DecoderOptions {
+        self.max_output_size = size;
+        self
+    }
+
+    /// Checks `token` before any decode function unpacks a level, and before
+    /// each row of blocks while converting to pixels. Once it is cancelled,
+    /// decoding fails with `Cancelled`, or returns `None` from the functions
+    /// that return an `Option`.
+    pub fn with_cancel_token(mut self, token: CancelToken) -> DecoderOptions {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Writes the pixels of `decode_level_to_rgba` and
+    /// `decode_levels_to_rgba` in `order` instead of RGBA.
+    pub fn with_channel_order(mut self, order: ChannelOrder) -> DecoderOptions {
+        self.channel_order = order;
+        self
+    }
+
+    /// Writes the pixels of `decode_level_to_rgba` and
+    /// `decode_levels_to_rgba` as `format`, after reordering their channels.
+    pub fn with_pixel_format(mut self, format: PixelFormat) -> DecoderOptions {
+        self.pixel_format = format;
+        self
+    }
+
+    /// Computes the Z of DxN normal maps into blue when transcoding to
+    /// pixels, giving a complete tangent-space normal map. X and Y are in
+    /// red and green for both DxN variants.
+    pub fn with_reconstruct_z(mut self, reconstruct: bool) -> DecoderOptions {
+        self.reconstruct_z = reconstruct;
+        self
+    }
+
+    /// Restores the channel order of the DXT5 xGxR, xGBR and AGBR formats
+    /// when transcoding to pixels. Without it their texels come out as
+    /// stored, e.g. with red in alpha.
+    pub fn with_unswizzle(mut self, unswizzle: bool) -> DecoderOptions {
+        self.unswizzle = unswizzle;
+        self
+    }
+
+    /// Multiplies the color channels by alpha when transcoding to pixels.
+    pub fn with_premultiply_alpha(mut self, premultiply: bool) -> DecoderOptions {
+        self.premultiply_alpha = premultiply;
+        self
+    }
+
+    /// Tags the texture's colors as encoded in `space`, which
+    /// `decode_level_to_linear` and `with_output_color_space` convert from.
+    /// sRGB by default.
+    pub fn with_color_space(mut self, space: ColorSpace) -> DecoderOptions {
+        self.color_space = space;
+        self
+    }
+
+    /// Converts the colors to `space` when transcoding to 8-bit pixels.
+    /// Alpha is left alone.
+    pub fn with_output_color_space(mut self, space: ColorSpace) -> DecoderOptions {
+        self.output_color_space = Some(space);
+        self
+    }
+
+    /// Applies ordered dithering when transcoding to the 16-bit pixel
+    /// formats, trading banding on gradients for a fine regular pattern.
+    pub fn with_dither(mut self, dither: bool) -> DecoderOptions {
+        self.dither = dither;
+        self
+    }
+
+    /// Lays out the faces of cubemap levels in `convention`'s face order and
+    /// orientation rather than crunch's, which is Direct3D's. Applies to every
+    /// function that decodes blocks, `decode_level` and `decode_face`
+    /// included; turning faces fails with `FlipUnsupported` for ETC formats.
+    pub fn with_cube_convention(mut self, convention: CubeConvention) -> DecoderOptions {
+        self.cube_convention = convention;
+        self
+    }
+
+    pub fn row_alignment(&self) -> u32 {
+        self.row_alignment
+    }
+
+    pub fn flip_vertical(&self) -> bool {
+        self.flip_vertical
+    }
+
+    pub fn max_output_size(&self) -> usize {
+        self.max_output_size
+    }
+
+    pub fn cancel_token(&self) -> Option<&CancelToken> {
+        self.cancel.as_ref()
+    }
+
+    pub fn channel_order(&self) -> ChannelOrder {
+        self.channel_order
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    pub fn reconstruct_z(&self) -> bool {
+        self.reconstruct_z
+    }
+
+    pub fn unswizzle(&self) -> bool {
+        self.unswizzle
+    }
+
+    pub fn premultiply_alpha(&self) -> bool {
+        self.premultiply_alpha
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn output_color_space(&self) -> Option<ColorSpace> {
+        self.output_color_space
+    }
+
+    pub fn dither(&self) -> bool {
+        self.dither
+    }
+
+    pub fn cube_convention(&self) -> CubeConvention {
+        self.cube_convention
+    }
+}
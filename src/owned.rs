@@ -0,0 +1,153 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Crunched textures that own their bytes.
+
+use std::fmt;
+use std::ops::Deref;
+use std::slice;
+use std::sync::Arc;
+
+use CrunchedData;
+use DecrunchError;
+
+/// Byte containers whose contents stay at the same address for as long as
+/// the container lives, even when it is moved.
+///
+/// # Safety
+///
+/// Every call to `as_ref` must return the same bytes at the same address
+/// until the value is dropped, and the bytes must not be changed in the
+/// meantime. Containers that keep their bytes inline, such as `[u8; N]`,
+/// move them along with the value and must not implement this trait.
+pub unsafe trait StableBytes: AsRef<[u8]> {}
+
+unsafe impl StableBytes for Vec<u8> {}
+unsafe impl StableBytes for Box<[u8]> {}
+unsafe impl StableBytes for Arc<[u8]> {}
+unsafe impl StableBytes for String {}
+unsafe impl StableBytes for &'static [u8] {}
+unsafe impl StableBytes for &'static str {}
+
+enum Storage {
+    Owned(Vec<u8>),
+    Shared(Arc<[u8]>),
+    Other(Box<dyn StableBytes + Send>),
+}
+
+impl Deref for Storage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            Storage::Owned(ref buffer) => buffer,
+            Storage::Shared(ref buffer) => buffer,
+            Storage::Other(ref buffer) => (**buffer).as_ref(),
+        }
+    }
+}
+
+/// A `CrunchedData` that owns its buffer, so it can be stored and moved
+/// without borrowing the input.
+pub struct CrunchedDataOwned {
+    // Declared first so the decoder context is freed before the bytes it was
+    // created from.
+    data: CrunchedData<'static>,
+    buffer: Storage,
+}
+
+// The context is only reachable through this value, no scratch allocator is
+// attached and the storage is `Send`, so it may be moved to another thread.
+unsafe impl Send for CrunchedDataOwned {}
+
+/// Extends the lifetime of `buffer`'s contents to that of the heap
+/// allocation, which neither moves with its owner nor is ever written to.
+fn detach(buffer: &[u8]) -> &'static [u8] {
+    unsafe { slice::from_raw_parts(buffer.as_ptr(), buffer.len()) }
+}
+
+impl CrunchedDataOwned {
+    pub fn new(buffer: Vec<u8>) -> Self {
+        Self::with_storage(Storage::Owned(buffer))
+    }
+
+    /// Like `new`, but validates the header and tables as
+    /// `CrunchedData::try_new` does.
+    pub fn try_new(buffer: Vec<u8>) -> Result<Self, DecrunchError> {
+        let buffer = Storage::Owned(buffer);
+        Ok(CrunchedDataOwned {
+            data: CrunchedData::try_new(detach(&buffer))?,
+            buffer,
+        })
+    }
+
+    /// Shares `buffer` with other textures created from it. Each gets its own
+    /// decoder context, so they can decode levels on separate threads while
+    /// the compressed bytes are kept in memory once.
+    pub fn from_shared(buffer: Arc<[u8]>) -> Self {
+        Self::with_storage(Storage::Shared(buffer))
+    }
+
+    /// Keeps a byte container alive for the texture without copying it, such
+    /// as a buffer received from the network or a memory map. The decoder
+    /// context refers to the bytes directly, so the container has to
+    /// implement `StableBytes`.
+    pub fn from_storage<S: StableBytes + Send + 'static>(storage: S) -> Self {
+        Self::with_storage(Storage::Other(Box::new(storage)))
+    }
+
+    fn with_storage(buffer: Storage) -> Self {
+        CrunchedDataOwned {
+            data: CrunchedData::new(detach(&buffer)),
+            buffer,
+        }
+    }
+
+    /// Borrows the texture for decoding.
+    pub fn data(&self) -> &CrunchedData<'_> {
+        &self.data
+    }
+
+    /// The compressed bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// `CrunchedData::try_cache_level` on the owned texture.
+    pub fn try_cache_level(&mut self, level: u32) -> Result<&[u8], DecrunchError> {
+        self.data.try_cache_level(level)
+    }
+
+    /// `CrunchedData::evict_level` on the owned texture.
+    pub fn evict_level(&mut self, level: u32) -> bool {
+        self.data.evict_level(level)
+    }
+
+    /// `CrunchedData::clear_cache` on the owned texture.
+    pub fn clear_cache(&mut self) {
+        self.data.clear_cache()
+    }
+}
+
+impl fmt::Debug for CrunchedDataOwned {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.data.fmt(f)
+    }
+}
@@ -0,0 +1,458 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Conversion of decoded blocks to pixels.
+
+use std::sync::mpsc;
+use std::thread;
+
+use analysis::texel_mask;
+use block;
+use CancelToken;
+use CrnFormat;
+use CrunchedData;
+use DecoderOptions;
+use LevelInfo;
+
+/// A level split into separate, row-major channel planes of `width * height`
+/// bytes each. The faces of a cubemap are stacked, so `height` covers all of
+/// them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Planes {
+    pub width: u32,
+    pub height: u32,
+    pub r: Vec<u8>,
+    pub g: Vec<u8>,
+    pub b: Vec<u8>,
+    pub a: Vec<u8>,
+}
+
+/// Order of the channels of a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOrder {
+    Rgba,
+    Argb,
+    Bgra,
+}
+
+impl ChannelOrder {
+    /// Reorders an RGBA pixel.
+    pub fn apply(self, [r, g, b, a]: [u8; 4]) -> [u8; 4] {
+        match self {
+            ChannelOrder::Rgba => [r, g, b, a],
+            ChannelOrder::Argb => [a, r, g, b],
+            ChannelOrder::Bgra => [b, g, r, a],
+        }
+    }
+}
+
+/// Pixel layout written by the transcoding functions. Packed 16-bit pixels
+/// are little-endian, with the first channel in the most significant bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba8,
+    /// The first three channels; alpha is dropped.
+    Rgb565,
+    Rgba4444,
+    /// The first channel only, e.g. the value of a DXT5A texture.
+    R8,
+    /// The first two channels, e.g. the X and Y of a DxN normal map.
+    Rg8,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba8 => 4,
+            PixelFormat::Rgb565 | PixelFormat::Rgba4444 => 2,
+            PixelFormat::R8 => 1,
+            PixelFormat::Rg8 => 2,
+        }
+    }
+
+    /// The largest value of each channel, for the formats with fewer than
+    /// 8 bits in some channel.
+    fn channel_max(self) -> Option<[u32; 4]> {
+        match self {
+            PixelFormat::Rgb565 => Some([31, 63, 31, 255]),
+            PixelFormat::Rgba4444 => Some([15; 4]),
+            _ => None,
+        }
+    }
+
+    /// Offsets a pixel at `(x, y)` by up to half a quantization step of this
+    /// format, following a 4x4 Bayer matrix, so that `write` spreads values
+    /// between two steps over neighboring pixels instead of banding.
+    pub(crate) fn dither(self, x: u32, y: u32, texel: [u8; 4]) -> [u8; 4] {
+        const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+        let max = match self.channel_max() {
+            Some(max) => max,
+            None => return texel,
+        };
+        let threshold = (f32::from(BAYER[(y % 4) as usize][(x % 4) as usize]) + 0.5) / 16.0 - 0.5;
+        let mut dithered = texel;
+        for (c, &max) in dithered.iter_mut().zip(max.iter()) {
+            let step = 255.0 / max as f32;
+            *c = (f32::from(*c) + threshold * step).round().clamp(0.0, 255.0) as u8;
+        }
+        dithered
+    }
+
+    /// Writes a pixel to the first `bytes_per_pixel` bytes of `out`.
+    pub(crate) fn write(self, [c0, c1, c2, c3]: [u8; 4], out: &mut [u8]) {
+        let scale = |value: u8, max: u32| (u32::from(value) * max + 127) / 255;
+        let packed = match self {
+            PixelFormat::Rgba8 => {
+                out[..4].copy_from_slice(&[c0, c1, c2, c3]);
+                return;
+            }
+            PixelFormat::R8 => {
+                out[0] = c0;
+                return;
+            }
+            PixelFormat::Rg8 => {
+                out[..2].copy_from_slice(&[c0, c1]);
+                return;
+            }
+            PixelFormat::Rgb565 => (scale(c0, 31) << 11) | (scale(c1, 63) << 5) | scale(c2, 31),
+            PixelFormat::Rgba4444 => [c0, c1, c2, c3]
+                .iter()
+                .fold(0, |acc, &c| (acc << 4) | scale(c, 15)),
+        };
+        out[..2].copy_from_slice(&(packed as u16).to_le_bytes());
+    }
+}
+
+/// How the color channels of a texture are encoded. CRN files do not say;
+/// Unity records it in the texture's `GraphicsFormat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+/// Byte order of packed pixels in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    /// Whatever the host uses; pixels are never byte-swapped.
+    Host,
+    Little,
+    Big,
+}
+
+/// Calls `f` with the position and RGBA value of every pixel inside the
+/// level, the faces of a cubemap stacked one below the other. Returns `false`
+/// if the format has no software decoder, or if `cancel` is cancelled, which
+/// is checked before each row of blocks.
+pub(crate) fn for_each_pixel<F: FnMut(u32, u32, [u8; 4])>(
+    info: &LevelInfo,
+    decoded: &[u8],
+    cancel: Option<&CancelToken>,
+    mut f: F,
+) -> bool {
+    let level_size = (info.blocks_x * info.blocks_y * info.bytes_per_block * info.faces) as usize;
+    if block::decode_rgba(info.format, &[0; 16]).is_none() || decoded.len() < level_size {
+        return false;
+    }
+    for (bx, by, block) in info.blocks(decoded) {
+        if bx == 0 && cancel.is_some_and(CancelToken::is_cancelled) {
+            return false;
+        }
+        let texels = block::decode_rgba(info.format, block).unwrap();
+        let mask = texel_mask(info, bx, by);
+        let top = by / info.blocks_y * info.height + by % info.blocks_y * 4;
+        for (i, (&t, &inside)) in texels.iter().zip(mask.iter()).enumerate() {
+            if inside {
+                f(bx * 4 + (i % 4) as u32, top + (i / 4) as u32, t);
+            }
+        }
+    }
+    true
+}
+
+/// Bytes taken by the pixels of a level at `bytes_per_pixel`, all faces
+/// included, or `None` if the size overflows.
+pub(crate) fn output_size(info: &LevelInfo, bytes_per_pixel: usize) -> Option<usize> {
+    (info.width as usize)
+        .checked_mul(info.height as usize)?
+        .checked_mul(info.faces as usize)?
+        .checked_mul(bytes_per_pixel)
+}
+
+/// Number of pixels of a level, all faces included.
+fn pixel_count(info: &LevelInfo) -> usize {
+    (info.width * info.height * info.faces) as usize
+}
+
+/// Planes of a decoded level, or `None` if the format has no software decoder
+/// or `cancel` is cancelled.
+pub(crate) fn planes(
+    info: &LevelInfo,
+    decoded: &[u8],
+    cancel: Option<&CancelToken>,
+) -> Option<Planes> {
+    let len = pixel_count(info);
+    let mut planes = Planes {
+        width: info.width,
+        height: info.height * info.faces,
+        r: vec![0; len],
+        g: vec![0; len],
+        b: vec![0; len],
+        a: vec![0; len],
+    };
+    let complete = for_each_pixel(info, decoded, cancel, |x, y, rgba| {
+        let i = (y * info.width + x) as usize;
+        planes.r[i] = rgba[0];
+        planes.g[i] = rgba[1];
+        planes.b[i] = rgba[2];
+        planes.a[i] = rgba[3];
+    });
+    if complete {
+        Some(planes)
+    } else {
+        None
+    }
+}
+
+/// Interleaved RGBA pixels of a decoded level, or `None` if the format has no
+/// software decoder.
+pub(crate) fn rgba(info: &LevelInfo, decoded: &[u8]) -> Option<Vec<u8>> {
+    let mut rgba = vec![0; pixel_count(info) * 4];
+    let complete = for_each_pixel(info, decoded, None, |x, y, texel| {
+        let i = (y * info.width + x) as usize * 4;
+        rgba[i..i + 4].copy_from_slice(&texel);
+    });
+    if complete {
+        Some(rgba)
+    } else {
+        None
+    }
+}
+
+/// Fills in the blue channel of a tangent-space normal whose X and Y are in
+/// red and green, mapping [-1, 1] to [0, 255].
+pub(crate) fn reconstruct_z([x, y, _, a]: [u8; 4]) -> [u8; 4] {
+    let unpack = |v: u8| f32::from(v) / 127.5 - 1.0;
+    let (nx, ny) = (unpack(x), unpack(y));
+    let nz = (1.0 - nx * nx - ny * ny).max(0.0).sqrt();
+    [x, y, ((nz + 1.0) * 127.5).round() as u8, a]
+}
+
+/// Moves the channels of a texel of a swizzled DXT5 format back to their
+/// places. Other formats are returned unchanged.
+pub(crate) fn unswizzle(format: CrnFormat, [r, g, b, a]: [u8; 4]) -> [u8; 4] {
+    match format {
+        CrnFormat::Dxt5xGxR => [a, g, 0, 255],
+        CrnFormat::Dxt5xGBR => [a, g, b, 255],
+        CrnFormat::Dxt5Agbr => [a, g, b, r],
+        _ => [r, g, b, a],
+    }
+}
+
+/// Scales the color channels of a texel by its alpha.
+pub(crate) fn premultiply([r, g, b, a]: [u8; 4]) -> [u8; 4] {
+    let scale = |c: u8| ((u32::from(c) * u32::from(a) + 127) / 255) as u8;
+    [scale(r), scale(g), scale(b), a]
+}
+
+/// Interleaved pixels of a decoded level in the layout set by `options`, or
+/// `None` if the format has no software decoder or the options' cancel token
+/// is cancelled.
+pub(crate) fn transcode(
+    info: &LevelInfo,
+    decoded: &[u8],
+    options: &DecoderOptions,
+) -> Option<Vec<u8>> {
+    let format = options.pixel_format;
+    let size = format.bytes_per_pixel();
+    let normal_z =
+        options.reconstruct_z && matches!(info.format, CrnFormat::DxNXy | CrnFormat::DxNYx);
+    let colors = options
+        .output_color_space
+        .and_then(|to| conversion_table(options.color_space, to));
+    let mut pixels = vec![0; pixel_count(info) * size];
+    let cancel = options.cancel.as_ref();
+    let complete = for_each_pixel(info, decoded, cancel, |x, y, mut texel| {
+        if options.unswizzle {
+            texel = unswizzle(info.format, texel);
+        }
+        if normal_z {
+            texel = reconstruct_z(texel);
+        }
+        if let Some(table) = &colors {
+            for channel in &mut texel[..3] {
+                *channel = table[*channel as usize];
+            }
+        }
+        if options.premultiply_alpha {
+            texel = premultiply(texel);
+        }
+        let mut texel = options.channel_order.apply(texel);
+        if options.dither {
+            texel = format.dither(x, y, texel);
+        }
+        let i = (y * info.width + x) as usize * size;
+        format.write(texel, &mut pixels[i..i + size]);
+    });
+    if complete {
+        Some(pixels)
+    } else {
+        None
+    }
+}
+
+/// Pixels of a decoded level packed into `u32`s, or `None` if the format has
+/// no software decoder or `cancel` is cancelled.
+pub(crate) fn packed(
+    info: &LevelInfo,
+    decoded: &[u8],
+    order: ChannelOrder,
+    endian: Endian,
+    cancel: Option<&CancelToken>,
+) -> Option<Vec<u32>> {
+    let mut pixels = vec![0; pixel_count(info)];
+    let complete = for_each_pixel(info, decoded, cancel, |x, y, texel| {
+        let value = u32::from_be_bytes(order.apply(texel));
+        pixels[(y * info.width + x) as usize] = match endian {
+            Endian::Host => value,
+            Endian::Little => value.to_le(),
+            Endian::Big => value.to_be(),
+        };
+    });
+    if complete {
+        Some(pixels)
+    } else {
+        None
+    }
+}
+
+/// The sRGB EOTF: an 8-bit sRGB encoded value as linear light in [0, 1].
+pub(crate) fn srgb_to_linear(value: u8) -> f32 {
+    let v = f32::from(value) / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The inverse of `srgb_to_linear`, rounded to 8 bits.
+pub(crate) fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let encoded = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// Maps 8-bit color values encoded in `from` to `to`, or `None` if they are
+/// the same.
+fn conversion_table(from: ColorSpace, to: ColorSpace) -> Option<[u8; 256]> {
+    let mut table = [0; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        *entry = match (from, to) {
+            (ColorSpace::Srgb, ColorSpace::Linear) => {
+                (srgb_to_linear(value as u8) * 255.0).round() as u8
+            }
+            (ColorSpace::Linear, ColorSpace::Srgb) => linear_to_srgb(value as f32 / 255.0),
+            _ => return None,
+        };
+    }
+    Some(table)
+}
+
+/// Linear-light RGBA pixels of a decoded level, decoding the color channels
+/// from `space`; alpha is always linear. `None` if the format has no software
+/// decoder or `cancel` is cancelled.
+pub(crate) fn linear(
+    info: &LevelInfo,
+    decoded: &[u8],
+    space: ColorSpace,
+    cancel: Option<&CancelToken>,
+) -> Option<Vec<f32>> {
+    let mut table = [0.0; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        *entry = match space {
+            ColorSpace::Srgb => srgb_to_linear(value as u8),
+            ColorSpace::Linear => value as f32 / 255.0,
+        };
+    }
+    let mut pixels = vec![0.0; pixel_count(info) * 4];
+    let complete = for_each_pixel(info, decoded, cancel, |x, y, texel| {
+        let i = (y * info.width + x) as usize * 4;
+        for channel in 0..3 {
+            pixels[i + channel] = table[texel[channel] as usize];
+        }
+        pixels[i + 3] = f32::from(texel[3]) / 255.0;
+    });
+    if complete {
+        Some(pixels)
+    } else {
+        None
+    }
+}
+
+/// Pixels of every level, as `transcode` lays them out. crunch unpacks a
+/// level in a single call, so the work is pipelined per level rather than per
+/// row chunk: levels are unpacked on the calling thread while a second thread
+/// converts the previous one.
+pub(crate) fn decode_levels_to_rgba(data: &CrunchedData) -> Option<Vec<Vec<u8>>> {
+    let texture_info = data.texture_info();
+    block::decode_rgba(texture_info.format, &[0; 16])?;
+
+    let options = data.options();
+    let (sender, receiver) = mpsc::sync_channel::<(LevelInfo, Vec<u8>)>(1);
+    thread::scope(|scope| {
+        let converter = scope.spawn(move || {
+            receiver
+                .iter()
+                .map(|(info, decoded)| transcode(&info, &decoded, options))
+                .collect::<Option<Vec<_>>>()
+        });
+        let mut unpacked = true;
+        let bytes_per_pixel = options.pixel_format.bytes_per_pixel();
+        for level in 0..texture_info.levels {
+            let size = output_size(&data.level_info(level), bytes_per_pixel);
+            if data.is_cancelled() || data.check_output_size(size).is_err() {
+                unpacked = false;
+                break;
+            }
+            let decoded = match data.decode_level(level) {
+                Some(decoded) => decoded,
+                None => {
+                    unpacked = false;
+                    break;
+                }
+            };
+            if sender.send((data.level_info(level), decoded)).is_err() {
+                break;
+            }
+        }
+        drop(sender);
+        let converted = converter.join().unwrap();
+        if unpacked {
+            converted
+        } else {
+            None
+        }
+    })
+}
@@ -0,0 +1,168 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Lazy decoding from a seekable source.
+
+use core::ffi::c_void;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crunch;
+use header::{read_be, Header, FLAG_SEGMENTED, HEADER_SIZE};
+use options::DEFAULT_MAX_OUTPUT_SIZE;
+use scratch;
+use LevelInfo;
+use TextureInfo;
+
+/// A crunched texture read from any `Read + Seek` source, such as a file or
+/// an archive entry.
+///
+/// Only the header, palettes and tables are read up front; each level's
+/// compressed data is read when the level is decoded. The texture may start
+/// anywhere in the source: its position when passed to `new` is taken as the
+/// start of the file.
+pub struct CrunchedReader<R> {
+    reader: R,
+    start: u64,
+    header: Header,
+    base: Vec<u8>,
+    ctx: *const c_void,
+    max_output_size: usize,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the complete header, level offsets included, from `reader`.
+pub(crate) fn read_header<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut header = vec![0; HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    let header_size = (read_be(&header, 2, 2) as usize).max(HEADER_SIZE);
+    header.resize(header_size, 0);
+    reader.read_exact(&mut header[HEADER_SIZE..])?;
+    Ok(header)
+}
+
+/// Appends exactly `size` bytes from `reader` to `buf`. The buffer grows as
+/// data arrives, so a bogus size in the header cannot force a huge
+/// allocation up front.
+fn read_to_vec<R: Read>(reader: &mut R, size: u64, buf: &mut Vec<u8>) -> io::Result<()> {
+    let read = reader.take(size).read_to_end(buf)?;
+    if read as u64 != size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Turns the start of a file into a segmented file, like crunch's
+/// `crnd_create_segmented_file`, so crunch accepts it without the level data.
+pub(crate) fn segment(base: &mut [u8]) {
+    let size = base.len() as u32;
+    base[6..10].copy_from_slice(&size.to_be_bytes());
+    let flags = read_be(base, 19, 2) | FLAG_SEGMENTED;
+    base[19..21].copy_from_slice(&(flags as u16).to_be_bytes());
+}
+
+impl<R: Read + Seek> CrunchedReader<R> {
+    /// Reads the header, palettes and tables from `reader`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let start = reader.stream_position()?;
+        let mut base = read_header(&mut reader)?;
+        let header_size = base.len();
+        let header =
+            Header::parse_prefix(&base).ok_or_else(|| invalid_data("invalid CRN header"))?;
+        header
+            .check()
+            .map_err(|err| invalid_data(&err.to_string()))?;
+        let rest = u64::from(header.base_size()).saturating_sub(header_size as u64);
+        read_to_vec(&mut reader, rest, &mut base)?;
+        segment(&mut base);
+
+        scratch::install();
+        let ctx = crunch::unpack_begin(&base);
+        if ctx.is_null() {
+            return Err(invalid_data("failed to decode CRN tables and palettes"));
+        }
+        Ok(CrunchedReader {
+            reader,
+            start,
+            header,
+            base,
+            ctx,
+            max_output_size: DEFAULT_MAX_OUTPUT_SIZE,
+        })
+    }
+
+    /// Fails `decode_level` with `InvalidData` for any level larger than
+    /// `size` bytes. Defaults to `DEFAULT_MAX_OUTPUT_SIZE`, as for
+    /// `DecoderOptions`.
+    pub fn with_max_output_size(mut self, size: usize) -> Self {
+        self.max_output_size = size;
+        self
+    }
+
+    pub fn level_info(&self, level: u32) -> LevelInfo {
+        crunch::get_level_info(&self.base, level).unwrap_or_default()
+    }
+
+    pub fn texture_info(&self) -> TextureInfo {
+        crunch::get_texture_info(&self.base).unwrap_or_default()
+    }
+
+    /// Reads the specified mipmap level's compressed data and transcodes it.
+    pub fn decode_level(&mut self, level: u32) -> io::Result<Vec<u8>> {
+        let size = match self.header.level_size(level) {
+            Some(size) if size > 0 => size,
+            _ => return Err(invalid_data("mipmap level out of range")),
+        };
+        let info = self.level_info(level);
+        let dst_size = (info.blocks_x as usize)
+            .checked_mul(info.blocks_y as usize)
+            .and_then(|n| n.checked_mul(info.bytes_per_block as usize))
+            .and_then(|n| n.checked_mul(info.faces as usize))
+            .filter(|&n| n <= self.max_output_size)
+            .ok_or_else(|| invalid_data("mipmap level too large"))?;
+
+        let ofs = self.header.level_ofs[level as usize];
+        self.reader
+            .seek(SeekFrom::Start(self.start + u64::from(ofs)))?;
+        let mut src = Vec::new();
+        read_to_vec(&mut self.reader, u64::from(size), &mut src)?;
+
+        let mut dst = vec![0; dst_size];
+        if !crunch::unpack_level_segmented(
+            self.ctx,
+            &src,
+            &mut dst,
+            info.faces,
+            info.blocks_x * info.bytes_per_block,
+            level,
+        ) {
+            return Err(invalid_data("failed to decode mipmap level"));
+        }
+        Ok(dst)
+    }
+}
+
+impl<R> Drop for CrunchedReader<R> {
+    fn drop(&mut self) {
+        crunch::unpack_end(self.ctx);
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Best-effort decoding of CRN files cut short, e.g. by an interrupted
+//! download or extraction.
+
+use std::fmt;
+use std::ops::Range;
+
+use header::{read_be, HEADER_SIZE, SIG_VALUE};
+use CrunchedData;
+
+/// Levels recovered from a truncated file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveredTexture {
+    /// Decoded data of each recovered level, starting at level 0.
+    pub levels: Vec<Vec<u8>>,
+    pub loss: TruncationLoss,
+}
+
+/// What was missing from a truncated file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TruncationLoss {
+    /// Data size recorded in the header.
+    pub expected_size: u32,
+    /// Bytes actually available.
+    pub actual_size: usize,
+    /// Levels whose data was incomplete or failed to decode.
+    pub lost_levels: Range<u32>,
+}
+
+impl TruncationLoss {
+    /// Whether anything was lost.
+    pub fn is_empty(&self) -> bool {
+        self.lost_levels.start == self.lost_levels.end
+    }
+}
+
+impl fmt::Display for TruncationLoss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} bytes present",
+            self.actual_size, self.expected_size
+        )?;
+        if !self.is_empty() {
+            write!(
+                f,
+                ", levels {} to {} lost",
+                self.lost_levels.start,
+                self.lost_levels.end - 1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes every level whose compressed data is fully present in `buffer`.
+///
+/// crunch refuses files shorter than the data size in their header, so the
+/// header is patched in a copy to cover only the complete levels. Returns
+/// `None` if the header, tables or palettes themselves are cut off.
+pub(crate) fn decode_truncated(buffer: &[u8]) -> Option<RecoveredTexture> {
+    if buffer.len() < HEADER_SIZE || read_be(buffer, 0, 2) != u32::from(SIG_VALUE) {
+        return None;
+    }
+    let header_size = read_be(buffer, 2, 2) as usize;
+    let data_size = read_be(buffer, 6, 4);
+    let levels = read_be(buffer, 16, 1);
+    if buffer.len() < header_size || header_size < 70 + 4 * levels as usize {
+        return None;
+    }
+
+    let level_ofs: Vec<usize> = (0..levels as usize)
+        .map(|i| read_be(buffer, 70 + 4 * i, 4) as usize)
+        .collect();
+    // The patched header is written into the copy, so every level must start
+    // after it and the offsets must not run backwards.
+    let first = *level_ofs.first()?;
+    let in_order = level_ofs
+        .iter()
+        .skip(1)
+        .chain(Some(&(data_size as usize)))
+        .zip(&level_ofs)
+        .all(|(next, &ofs)| ofs <= *next);
+    if first < header_size || !in_order {
+        return None;
+    }
+    let level_end = |i: usize| match level_ofs.get(i + 1) {
+        Some(&next) => next,
+        None => data_size as usize,
+    };
+    let complete = (0..levels as usize)
+        .take_while(|&i| level_end(i) <= buffer.len() && level_ofs[i] < level_end(i))
+        .count();
+    if complete == 0 {
+        return None;
+    }
+
+    let mut patched = buffer[..level_end(complete - 1)].to_vec();
+    let patched_size = patched.len() as u32;
+    patched[6..10].copy_from_slice(&patched_size.to_be_bytes());
+    patched[16] = complete as u8;
+
+    let c_data = CrunchedData::new(&patched);
+    let decoded: Vec<Vec<u8>> = (0..complete as u32)
+        .map_while(|level| c_data.decode_level(level))
+        .collect();
+    if decoded.is_empty() {
+        return None;
+    }
+
+    Some(RecoveredTexture {
+        loss: TruncationLoss {
+            expected_size: data_size,
+            actual_size: buffer.len(),
+            lost_levels: decoded.len() as u32..levels,
+        },
+        levels: decoded,
+    })
+}
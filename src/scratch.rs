@@ -0,0 +1,193 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Scratch allocation for crunch's internal buffers.
+//!
+//! crunch allocates its tables, palettes and block buffers through a single
+//! global pair of callbacks. This module installs callbacks that serve each
+//! request from the [`ScratchAllocator`] attached to the `CrunchedData` being
+//! worked on, or from the global heap when there is none. Scratch blocks are
+//! never freed individually; they are reclaimed when the allocator is reset.
+//!
+//! The callbacks are installed before the first context is created, by
+//! `crunch::unpack_begin` and `sys::crnd_unpack_begin`, so every block crunch
+//! frees was allocated by them.
+//!
+//! Implementing the trait for a `bumpalo::Bump` takes a few lines:
+//!
+//! ```ignore
+//! unsafe impl ScratchAllocator for Arena {
+//!     fn alloc(&self, size: usize, align: usize) -> *mut u8 {
+//!         let layout = Layout::from_size_align(size, align).unwrap();
+//!         match self.0.try_alloc_layout(layout) {
+//!             Ok(p) => p.as_ptr(),
+//!             Err(_) => ptr::null_mut(),
+//!         }
+//!     }
+//! }
+//! ```
+
+use core::ffi::c_void;
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::ptr;
+use std::sync::Once;
+
+use crunch;
+
+/// A source of memory that is only released in bulk.
+///
+/// # Safety
+///
+/// A non-null block returned by `alloc` must be valid for reads and writes of
+/// `size` bytes, aligned to `align` (a power of two, at most 16) and must not
+/// overlap any other live block. crunch writes a 16-byte header at its start
+/// and keeps using the block until the `CrunchedData` it was allocated for is
+/// dropped, so blocks must stay valid for as long as the allocator is
+/// borrowed, even if it is reset through `&self` in the meantime.
+pub unsafe trait ScratchAllocator {
+    /// Returns a block of at least `size` bytes aligned to `align`, or null
+    /// if the allocator is exhausted.
+    fn alloc(&self, size: usize, align: usize) -> *mut u8;
+}
+
+// Every block starts with a header recording its size and origin, so blocks can
+// be resized and freed without knowing which allocator served them.
+const HEADER_SIZE: usize = 16;
+const ALIGN: usize = 16;
+
+const KIND_HEAP: usize = 0;
+const KIND_SCRATCH: usize = 1;
+
+thread_local! {
+    static CURRENT: Cell<Option<*const (dyn ScratchAllocator + 'static)>> = const { Cell::new(None) };
+}
+
+static INSTALL: Once = Once::new();
+
+/// Installs the allocation callbacks. Must run before crunch allocates
+/// anything, so that every block it frees carries our header; every path that
+/// creates a context calls it first.
+pub(crate) fn install() {
+    INSTALL.call_once(|| crunch::set_memory_callbacks(realloc_callback, msize_callback));
+}
+
+/// Runs `f` with `scratch` serving crunch's allocations on this thread.
+pub(crate) fn with<R, F: FnOnce() -> R>(scratch: Option<&dyn ScratchAllocator>, f: F) -> R {
+    struct Restore(Option<*const (dyn ScratchAllocator + 'static)>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.with(|c| c.set(self.0));
+        }
+    }
+
+    // The pointer only lives in CURRENT while `scratch` is borrowed.
+    let scratch = scratch.map(|s| unsafe {
+        let s: *const dyn ScratchAllocator = s;
+        #[allow(clippy::missing_transmute_annotations)]
+        std::mem::transmute(s)
+    });
+    let _restore = Restore(CURRENT.with(|c| c.replace(scratch)));
+    f()
+}
+
+unsafe fn header(p: *mut c_void) -> *mut usize {
+    (p as *mut u8).sub(HEADER_SIZE) as *mut usize
+}
+
+fn allocate(size: usize) -> *mut c_void {
+    let total = match size.checked_add(HEADER_SIZE) {
+        Some(total) => total,
+        None => return ptr::null_mut(),
+    };
+    let (block, kind) = match CURRENT.with(|c| c.get()) {
+        Some(scratch) => (unsafe { (*scratch).alloc(total, ALIGN) }, KIND_SCRATCH),
+        None => match Layout::from_size_align(total, ALIGN) {
+            Ok(layout) => (unsafe { alloc::alloc(layout) }, KIND_HEAP),
+            Err(_) => return ptr::null_mut(),
+        },
+    };
+    if block.is_null() {
+        return ptr::null_mut();
+    }
+    unsafe {
+        let h = block as *mut usize;
+        *h = size;
+        *h.add(1) = kind;
+        block.add(HEADER_SIZE) as *mut c_void
+    }
+}
+
+unsafe fn release(p: *mut c_void) {
+    let h = header(p);
+    if *h.add(1) == KIND_HEAP {
+        let layout = Layout::from_size_align_unchecked(*h + HEADER_SIZE, ALIGN);
+        alloc::dealloc(h as *mut u8, layout);
+    }
+}
+
+extern "C" fn realloc_callback(
+    p: *mut c_void,
+    size: usize,
+    actual_size: *mut usize,
+    movable: bool,
+    _user_data: *mut c_void,
+) -> *mut c_void {
+    let result = if p.is_null() {
+        allocate(size)
+    } else if size == 0 {
+        unsafe { release(p) };
+        ptr::null_mut()
+    } else {
+        let old_size = unsafe { *header(p) };
+        if size <= old_size {
+            p
+        } else if !movable {
+            ptr::null_mut()
+        } else {
+            let new = allocate(size);
+            if !new.is_null() {
+                unsafe {
+                    ptr::copy_nonoverlapping(p as *const u8, new as *mut u8, old_size);
+                    release(p);
+                }
+            }
+            new
+        }
+    };
+
+    if !actual_size.is_null() {
+        let size = if result.is_null() {
+            0
+        } else {
+            unsafe { *header(result) }
+        };
+        unsafe { *actual_size = size };
+    }
+    result
+}
+
+extern "C" fn msize_callback(p: *mut c_void, _user_data: *mut c_void) -> usize {
+    if p.is_null() {
+        0
+    } else {
+        unsafe { *header(p) }
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Cross-checks between the info structs reported by crunch, the raw header
+//! and the compressed level data.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use header::{self, Header};
+use CrnFormat;
+use CrunchedData;
+
+/// An inconsistency found by [`CrunchedData::check_level`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inconsistency {
+    /// The header could not be parsed.
+    Header,
+    LevelOutOfRange {
+        level: u32,
+        levels: u32,
+    },
+    Dimensions {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    BlockCount {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    BytesPerBlock {
+        expected: u32,
+        actual: u32,
+    },
+    Faces {
+        expected: u32,
+        actual: u32,
+    },
+    /// The level's compressed data does not lie within the file's data.
+    Segment {
+        level: u32,
+        offset: u32,
+        data_size: u32,
+    },
+    /// All checks passed but crunch failed to decode the level.
+    DecodeFailed,
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Inconsistency::Header => write!(f, "invalid CRN header"),
+            Inconsistency::LevelOutOfRange { level, levels } => {
+                write!(f, "level {} out of range ({} levels)", level, levels)
+            }
+            Inconsistency::Dimensions { expected, actual } => write!(
+                f,
+                "dimensions {}x{} do not match header ({}x{})",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            Inconsistency::BlockCount { expected, actual } => write!(
+                f,
+                "block count {}x{} does not match header ({}x{})",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            Inconsistency::BytesPerBlock { expected, actual } => write!(
+                f,
+                "{} bytes per block does not match format ({})",
+                actual, expected
+            ),
+            Inconsistency::Faces { expected, actual } => {
+                write!(f, "{} faces does not match header ({})", actual, expected)
+            }
+            Inconsistency::Segment {
+                level,
+                offset,
+                data_size,
+            } => write!(
+                f,
+                "level {} data at offset {} lies outside the {} byte payload",
+                level, offset, data_size
+            ),
+            Inconsistency::DecodeFailed => write!(f, "decode failed"),
+        }
+    }
+}
+
+/// Bytes per block for a raw header format, or 0 if it is not a format.
+fn format_bytes_per_block(format: u32) -> u32 {
+    CrnFormat::try_from(format as i32).map_or(0, CrnFormat::bytes_per_block)
+}
+
+pub(crate) fn check_level(c_data: &CrunchedData, level: u32) -> Result<(), Inconsistency> {
+    let header = match Header::parse(c_data.buffer) {
+        Some(h) => h,
+        None => return Err(Inconsistency::Header),
+    };
+    // The level count byte is not validated, so also bound `level` by the
+    // mip chain the dimensions allow, which keeps the shifts below in range.
+    let levels = header
+        .levels
+        .min(header::max_mips(header.width, header.height));
+    if level >= levels {
+        return Err(Inconsistency::LevelOutOfRange { level, levels });
+    }
+
+    let info = c_data.level_info(level);
+
+    let width = (header.width >> level).max(1);
+    let height = (header.height >> level).max(1);
+    if (info.width, info.height) != (width, height) {
+        return Err(Inconsistency::Dimensions {
+            expected: (width, height),
+            actual: (info.width, info.height),
+        });
+    }
+
+    let blocks = ((width + 3) >> 2, (height + 3) >> 2);
+    if (info.blocks_x, info.blocks_y) != blocks {
+        return Err(Inconsistency::BlockCount {
+            expected: blocks,
+            actual: (info.blocks_x, info.blocks_y),
+        });
+    }
+
+    let bytes_per_block = format_bytes_per_block(header.format);
+    if info.bytes_per_block != bytes_per_block {
+        return Err(Inconsistency::BytesPerBlock {
+            expected: bytes_per_block,
+            actual: info.bytes_per_block,
+        });
+    }
+
+    if info.faces != header.faces {
+        return Err(Inconsistency::Faces {
+            expected: header.faces,
+            actual: info.faces,
+        });
+    }
+
+    if !header.level_in_bounds(level) {
+        return Err(Inconsistency::Segment {
+            level,
+            offset: header.level_ofs[level as usize],
+            data_size: header.data_size,
+        });
+    }
+
+    Ok(())
+}
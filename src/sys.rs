@@ -0,0 +1,176 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Raw declarations of the crunch functions built into this crate, for uses
+//! the safe API does not cover.
+//!
+//! `crnd_get_palette` and `crnd_unpack_level_segmented` are additions of this
+//! crate. With the `simd` feature, `CrunchedData` may create its context with
+//! a separate AVX2 build of the decoder, while these functions are always
+//! the baseline build. With that feature, a context from
+//! `CrunchedData::as_raw_ctx` must not be passed to these functions, and a
+//! context from `crnd_unpack_begin` must not be passed to
+//! `CrunchedData::from_raw_ctx`.
+//!
+//! crunch frees a context with the memory callbacks installed at the time, so
+//! a context handed to `CrunchedData::from_raw_ctx` must have been allocated
+//! through the crate's callbacks. `crnd_unpack_begin` below installs them
+//! before creating the context; crunch's own entry point is not exposed.
+//! `crnd_set_memory_callbacks` replaces them for good: contexts created
+//! before the call, including those owned by a `CrunchedData`, must not be
+//! used or dropped after it, and `CrunchedData::new_in` no longer uses its
+//! scratch allocator.
+
+use core::ffi::c_void;
+
+use scratch;
+
+/// `crn_level_info`.
+#[repr(C)]
+#[derive(Default)]
+pub struct RawLevelInfo {
+    pub struct_size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub faces: u32,
+    pub blocks_x: u32,
+    pub blocks_y: u32,
+    pub bytes_per_block: u32,
+    pub format: i32,
+}
+
+/// `crn_texture_info`.
+#[repr(C)]
+#[derive(Default)]
+pub struct RawTextureInfo {
+    pub struct_size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub levels: u32,
+    pub faces: u32,
+    pub bytes_per_block: u32,
+    pub userdata0: u32,
+    pub userdata1: u32,
+    pub format: i32,
+}
+
+extern "C" {
+    pub fn crnd_get_level_info(
+        pData: *const u8,
+        data_size: u32,
+        level_index: u32,
+        pLevel_info: *mut RawLevelInfo,
+    ) -> bool;
+
+    pub fn crnd_get_texture_info(
+        pData: *const u8,
+        data_size: u32,
+        pTexture_info: *mut RawTextureInfo,
+    ) -> bool;
+
+    pub fn crnd_unpack_end(ctx: *const c_void) -> bool;
+
+    pub fn crnd_unpack_level(
+        pContext: *const c_void,
+        ppDst: *const *const u8,
+        dst_size_in_bytes: u32,
+        row_pitch_in_bytes: u32,
+        level_index: u32,
+    ) -> bool;
+
+    pub fn crnd_unpack_level_segmented(
+        pContext: *const c_void,
+        pSrc: *const u8,
+        src_size_in_bytes: u32,
+        ppDst: *const *const u8,
+        dst_size_in_bytes: u32,
+        row_pitch_in_bytes: u32,
+        level_index: u32,
+    ) -> bool;
+
+    /// `which` is 0 for color endpoints, 1 for color selectors and 2 for
+    /// alpha endpoints.
+    pub fn crnd_get_palette(
+        pContext: *const c_void,
+        which: u32,
+        ppData: *mut *const c_void,
+        pCount: *mut u32,
+    ) -> bool;
+}
+
+// Entry points that must not run before the crate's memory callbacks are
+// installed; the public versions below install them first.
+pub(crate) mod raw {
+    use core::ffi::c_void;
+
+    use super::{MSizeFunc, ReallocFunc};
+
+    extern "C" {
+        pub fn crnd_unpack_begin(pData: *const u8, data_size: u32) -> *const c_void;
+
+        pub fn crnd_set_memory_callbacks(
+            pRealloc: ReallocFunc,
+            pMSize: MSizeFunc,
+            pUser_data: *mut c_void,
+        );
+    }
+}
+
+/// `crnd_unpack_begin`. Installs the crate's memory callbacks first, so the
+/// context can be passed to `CrunchedData::from_raw_ctx` unless the `simd`
+/// feature is enabled.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `data_size` bytes for as long as the
+/// context lives.
+pub unsafe fn crnd_unpack_begin(data: *const u8, data_size: u32) -> *const c_void {
+    scratch::install();
+    raw::crnd_unpack_begin(data, data_size)
+}
+
+/// `crnd_set_memory_callbacks`. The crate's callbacks are installed first,
+/// so they never replace these later.
+///
+/// # Safety
+///
+/// Contexts created before the call, including those owned by a
+/// `CrunchedData`, must not be freed or decoded from after it.
+pub unsafe fn crnd_set_memory_callbacks(
+    realloc: ReallocFunc,
+    msize: MSizeFunc,
+    user_data: *mut c_void,
+) {
+    scratch::install();
+    raw::crnd_set_memory_callbacks(realloc, msize, user_data)
+}
+
+/// `crnd_realloc_func`: allocates when `p` is null, frees when `size` is 0 and
+/// resizes otherwise, only moving the block if `movable` is set.
+pub type ReallocFunc = extern "C" fn(
+    p: *mut c_void,
+    size: usize,
+    actual_size: *mut usize,
+    movable: bool,
+    user_data: *mut c_void,
+) -> *mut c_void;
+
+/// `crnd_msize_func`: returns the usable size of a block.
+pub type MSizeFunc = extern "C" fn(p: *mut c_void, user_data: *mut c_void) -> usize;
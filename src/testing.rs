@@ -0,0 +1,294 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Reference vectors for pinning decode behaviour in downstream tests.
+//!
+//! [`emit`] decodes every level of a set of fixtures and records a hash of the
+//! output together with a small tile of raw blocks from the top-left corner.
+//! The vectors can be written to a plain text file with [`write_vectors`],
+//! committed next to the fixtures, and checked later with [`verify`].
+//!
+//! The [`approval`] module compares decoded pixels with PNG snapshots instead.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str;
+
+use CrunchedData;
+
+pub mod approval;
+
+/// Number of blocks from the first block row that are stored verbatim.
+pub const TILE_BLOCKS: u32 = 4;
+
+/// Canonical decode output for a single level of a fixture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceVector {
+    pub name: String,
+    pub level: u32,
+    pub len: usize,
+    pub hash: u64,
+    pub tile: Vec<u8>,
+}
+
+/// Describes the first difference found by [`verify`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// The level could not be decoded.
+    DecodeFailed {
+        name: String,
+        level: u32,
+    },
+    Length {
+        name: String,
+        level: u32,
+        expected: usize,
+        actual: usize,
+    },
+    Tile {
+        name: String,
+        level: u32,
+    },
+    Hash {
+        name: String,
+        level: u32,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mismatch::DecodeFailed { ref name, level } => {
+                write!(f, "{} level {}: decode failed", name, level)
+            }
+            Mismatch::Length {
+                ref name,
+                level,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} level {}: expected {} bytes, got {}",
+                name, level, expected, actual
+            ),
+            Mismatch::Tile { ref name, level } => {
+                write!(f, "{} level {}: reference tile differs", name, level)
+            }
+            Mismatch::Hash {
+                ref name,
+                level,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} level {}: expected hash {:016x}, got {:016x}",
+                name, level, expected, actual
+            ),
+        }
+    }
+}
+
+impl ReferenceVector {
+    /// Formats the vector as a single whitespace-separated line. Whitespace
+    /// and `%` in the name are percent-escaped.
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {} {} {:016x} {}",
+            escape(&self.name),
+            self.level,
+            self.len,
+            self.hash,
+            to_hex(&self.tile)
+        )
+    }
+
+    /// Parses a line previously produced by [`ReferenceVector::to_line`].
+    pub fn from_line(line: &str) -> Option<ReferenceVector> {
+        let mut parts = line.split_whitespace();
+        let name = unescape(parts.next()?)?;
+        let level = parts.next()?.parse().ok()?;
+        let len = parts.next()?.parse().ok()?;
+        let hash = u64::from_str_radix(parts.next()?, 16).ok()?;
+        let tile = from_hex(parts.next().unwrap_or(""))?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(ReferenceVector {
+            name,
+            level,
+            len,
+            hash,
+            tile,
+        })
+    }
+}
+
+/// 64-bit FNV-1a hash used for the reference vectors.
+pub fn hash(data: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in data {
+        h ^= u64::from(b);
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h
+}
+
+/// Decodes every level of each `(name, data)` fixture and records its
+/// reference vector. Returns `None` if any level fails to decode.
+pub fn emit(fixtures: &[(&str, &[u8])]) -> Option<Vec<ReferenceVector>> {
+    let mut vectors = Vec::new();
+    for &(name, data) in fixtures {
+        let c_data = CrunchedData::new(data);
+        for level in 0..c_data.texture_info().levels {
+            let decoded = c_data.decode_level(level)?;
+            vectors.push(ReferenceVector {
+                name: name.to_string(),
+                level,
+                len: decoded.len(),
+                hash: hash(&decoded),
+                tile: tile(&c_data, level, &decoded).to_vec(),
+            });
+        }
+    }
+    Some(vectors)
+}
+
+/// Decodes the levels named in `vectors` from `data` and compares them
+/// against the recorded values.
+pub fn verify(data: &[u8], vectors: &[ReferenceVector]) -> Result<(), Mismatch> {
+    let c_data = CrunchedData::new(data);
+    for v in vectors {
+        let decoded = match c_data.decode_level(v.level) {
+            Some(res) => res,
+            None => {
+                return Err(Mismatch::DecodeFailed {
+                    name: v.name.clone(),
+                    level: v.level,
+                })
+            }
+        };
+        if decoded.len() != v.len {
+            return Err(Mismatch::Length {
+                name: v.name.clone(),
+                level: v.level,
+                expected: v.len,
+                actual: decoded.len(),
+            });
+        }
+        if tile(&c_data, v.level, &decoded) != &v.tile[..] {
+            return Err(Mismatch::Tile {
+                name: v.name.clone(),
+                level: v.level,
+            });
+        }
+        let actual = hash(&decoded);
+        if actual != v.hash {
+            return Err(Mismatch::Hash {
+                name: v.name.clone(),
+                level: v.level,
+                expected: v.hash,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Writes one vector per line.
+pub fn write_vectors<W: Write>(mut w: W, vectors: &[ReferenceVector]) -> io::Result<()> {
+    for v in vectors {
+        writeln!(w, "{}", v.to_line())?;
+    }
+    Ok(())
+}
+
+/// Reads vectors written by [`write_vectors`]. Blank lines and lines starting
+/// with `#` are ignored.
+pub fn read_vectors<R: BufRead>(r: R) -> io::Result<Vec<ReferenceVector>> {
+    let mut vectors = Vec::new();
+    for line in r.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match ReferenceVector::from_line(line) {
+            Some(v) => vectors.push(v),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed reference vector: {}", line),
+                ))
+            }
+        }
+    }
+    Ok(vectors)
+}
+
+fn tile<'b>(c_data: &CrunchedData, level: u32, decoded: &'b [u8]) -> &'b [u8] {
+    let info = c_data.level_info(level);
+    let len = (info.blocks_x.min(TILE_BLOCKS) * info.bytes_per_block) as usize;
+    &decoded[..len.min(decoded.len())]
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn escape(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c == '%' || c.is_whitespace() {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                out.push_str(&format!("%{:02x}", b));
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            out.push(u8::from_str_radix(str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
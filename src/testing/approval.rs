@@ -0,0 +1,390 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Golden-image approval tests for decoded textures.
+//!
+//! [`Approval::check_level`] decodes a level to RGBA and compares it with a PNG
+//! snapshot in the snapshot directory. The first run records the snapshot;
+//! later runs fail if any channel differs by more than the tolerance, and
+//! leave the new output next to the snapshot as `<name>.actual.png` until the
+//! next passing run. Delete a snapshot to record it again.
+//!
+//! To stay free of dependencies, snapshots are written as PNGs made of stored
+//! (uncompressed) deflate blocks with no row filters, and [`read_png`] only
+//! accepts that subset. Any other PNG, such as a snapshot re-saved by an
+//! image editor, is rejected with `InvalidData` rather than misread.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use pixels;
+use CrunchedData;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Result of a successful check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// No snapshot existed, so one was written.
+    Recorded,
+    Matched,
+}
+
+#[derive(Debug)]
+pub enum ApprovalError {
+    Io(io::Error),
+    /// The level could not be decoded to RGBA.
+    DecodeFailed,
+    /// The pixel buffer does not hold `width * height` RGBA pixels.
+    Length {
+        expected: usize,
+        actual: usize,
+    },
+    Size {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// Pixels differ by more than the tolerance.
+    Pixels {
+        differing: usize,
+        first: (u32, u32),
+        expected: [u8; 4],
+        actual: [u8; 4],
+    },
+}
+
+impl fmt::Display for ApprovalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApprovalError::Io(ref err) => write!(f, "snapshot I/O failed: {}", err),
+            ApprovalError::DecodeFailed => write!(f, "level could not be decoded to RGBA"),
+            ApprovalError::Length { expected, actual } => write!(
+                f,
+                "pixel buffer holds {} bytes instead of {}",
+                actual, expected
+            ),
+            ApprovalError::Size { expected, actual } => write!(
+                f,
+                "size {}x{} does not match snapshot size {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            ApprovalError::Pixels {
+                differing,
+                first,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} pixels differ, first at ({}, {}): {:?} instead of {:?}",
+                differing, first.0, first.1, actual, expected
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for ApprovalError {
+    fn from(err: io::Error) -> ApprovalError {
+        ApprovalError::Io(err)
+    }
+}
+
+/// Checks decoded output against snapshots in a directory.
+#[derive(Debug, Clone)]
+pub struct Approval {
+    dir: PathBuf,
+    tolerance: u8,
+}
+
+impl Approval {
+    /// Uses snapshots in `dir`, which is created when the first snapshot is
+    /// recorded. Pixels must match exactly until `with_tolerance` is used.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Approval {
+        Approval {
+            dir: dir.as_ref().to_path_buf(),
+            tolerance: 0,
+        }
+    }
+
+    /// Allows every channel to differ from the snapshot by up to `tolerance`.
+    pub fn with_tolerance(mut self, tolerance: u8) -> Approval {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Decodes a level to RGBA and checks it against the snapshot
+    /// `<name>_level<level>.png`.
+    pub fn check_level(
+        &self,
+        name: &str,
+        data: &CrunchedData,
+        level: u32,
+    ) -> Result<Outcome, ApprovalError> {
+        let info = data.level_info(level);
+        let rgba = data
+            .decode_level(level)
+            .and_then(|decoded| pixels::rgba(&info, &decoded))
+            .ok_or(ApprovalError::DecodeFailed)?;
+        self.check_rgba(
+            &format!("{}_level{}", name, level),
+            info.width,
+            info.height,
+            &rgba,
+        )
+    }
+
+    /// Checks interleaved RGBA pixels against the snapshot `<name>.png`.
+    pub fn check_rgba(
+        &self,
+        name: &str,
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+    ) -> Result<Outcome, ApprovalError> {
+        let expected = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|n| n.checked_mul(4));
+        if expected != Some(rgba.len()) {
+            return Err(ApprovalError::Length {
+                expected: expected.unwrap_or(usize::MAX),
+                actual: rgba.len(),
+            });
+        }
+
+        let path = self.dir.join(format!("{}.png", name));
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                fs::create_dir_all(&self.dir)?;
+                write_png(BufWriter::new(File::create(&path)?), width, height, rgba)?;
+                return Ok(Outcome::Recorded);
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let (expected_width, expected_height, expected) = read_png(BufReader::new(file))?;
+
+        let result = if (expected_width, expected_height) != (width, height) {
+            Err(ApprovalError::Size {
+                expected: (expected_width, expected_height),
+                actual: (width, height),
+            })
+        } else {
+            self.compare(width, &expected, rgba)
+        };
+        let actual = self.dir.join(format!("{}.actual.png", name));
+        if result.is_err() {
+            write_png(BufWriter::new(File::create(actual)?), width, height, rgba)?;
+        } else if let Err(err) = fs::remove_file(actual) {
+            if err.kind() != io::ErrorKind::NotFound {
+                return Err(err.into());
+            }
+        }
+        result
+    }
+
+    fn compare(
+        &self,
+        width: u32,
+        expected: &[u8],
+        actual: &[u8],
+    ) -> Result<Outcome, ApprovalError> {
+        let mut differing = 0;
+        let mut first = None;
+        for (i, (e, a)) in expected.chunks(4).zip(actual.chunks(4)).enumerate() {
+            if e.iter()
+                .zip(a)
+                .any(|(&e, &a)| e.abs_diff(a) > self.tolerance)
+            {
+                differing += 1;
+                first = first.or(Some((i as u32, e, a)));
+            }
+        }
+        match first {
+            None => Ok(Outcome::Matched),
+            Some((i, e, a)) => Err(ApprovalError::Pixels {
+                differing,
+                first: (i % width, i / width),
+                expected: [e[0], e[1], e[2], e[3]],
+                actual: [a[0], a[1], a[2], a[3]],
+            }),
+        }
+    }
+}
+
+pub(crate) fn crc32(data: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for &bytes in data {
+        for &b in bytes {
+            crc ^= u32::from(b);
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    w.write_all(&crc32(&[kind, data]).to_be_bytes())
+}
+
+/// Writes 8-bit RGBA pixels as an uncompressed PNG.
+pub fn write_png<W: Write>(mut w: W, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    let mut raw = Vec::with_capacity(rgba.len() + height as usize);
+    for row in rgba.chunks((width as usize * 4).max(1)) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    // zlib stream of stored deflate blocks.
+    let mut idat = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        idat.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        idat.push(blocks.peek().is_none() as u8);
+        idat.extend_from_slice(&len.to_le_bytes());
+        idat.extend_from_slice(&(!len).to_le_bytes());
+        idat.extend_from_slice(block);
+    }
+    idat.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    w.write_all(&PNG_SIGNATURE)?;
+    write_chunk(&mut w, b"IHDR", &ihdr)?;
+    write_chunk(&mut w, b"IDAT", &idat)?;
+    write_chunk(&mut w, b"IEND", &[])?;
+    w.flush()
+}
+
+/// Reads a PNG written by [`write_png`], returning its width, height and
+/// RGBA pixels. Compressed or filtered PNGs are rejected, as are chunks or
+/// deflate blocks whose checksums do not match.
+pub fn read_png<R: Read>(mut r: R) -> io::Result<(u32, u32, Vec<u8>)> {
+    let unsupported = || io::Error::new(io::ErrorKind::InvalidData, "unsupported PNG snapshot");
+
+    let mut signature = [0; 8];
+    r.read_exact(&mut signature)?;
+    if signature != PNG_SIGNATURE {
+        return Err(unsupported());
+    }
+    let mut size = None;
+    let mut idat = Vec::new();
+    loop {
+        let mut header = [0; 8];
+        r.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let mut data = Vec::new();
+        (&mut r).take(u64::from(len) + 4).read_to_end(&mut data)?;
+        if data.len() != len as usize + 4 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let crc = data.split_off(len as usize);
+        if crc32(&[&header[4..], &data]).to_be_bytes() != crc[..] {
+            return Err(unsupported());
+        }
+        match &header[4..] {
+            b"IHDR" => {
+                if data.len() != 13 || data[8..] != [8, 6, 0, 0, 0] {
+                    return Err(unsupported());
+                }
+                let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+                size = Some((width, height));
+            }
+            b"IDAT" => idat.extend_from_slice(&data),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    let (width, height) = size.ok_or_else(unsupported)?;
+
+    // A zlib header for deflate without a preset dictionary, then stored
+    // blocks only.
+    match idat.get(..2) {
+        Some(&[cmf, flg]) if cmf & 0x0F == 8 && flg & 0x20 == 0 => {
+            if (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+                return Err(unsupported());
+            }
+        }
+        _ => return Err(unsupported()),
+    }
+    let mut raw = Vec::new();
+    let mut pos = 2;
+    loop {
+        let header = *idat.get(pos).ok_or_else(unsupported)?;
+        let block = idat.get(pos + 1..pos + 5).ok_or_else(unsupported)?;
+        if header & 0b110 != 0 {
+            return Err(unsupported());
+        }
+        let len = u16::from_le_bytes([block[0], block[1]]);
+        if u16::from_le_bytes([block[2], block[3]]) != !len {
+            return Err(unsupported());
+        }
+        let len = len as usize;
+        raw.extend_from_slice(idat.get(pos + 5..pos + 5 + len).ok_or_else(unsupported)?);
+        pos += 5 + len;
+        if header & 1 != 0 {
+            break;
+        }
+    }
+    if idat.get(pos..) != Some(&adler32(&raw).to_be_bytes()[..]) {
+        return Err(unsupported());
+    }
+
+    let stride = (width as usize).checked_mul(4).ok_or_else(unsupported)?;
+    let size = (stride + 1)
+        .checked_mul(height as usize)
+        .ok_or_else(unsupported)?;
+    if raw.len() != size {
+        return Err(unsupported());
+    }
+    let mut rgba = Vec::with_capacity(size - height as usize);
+    for row in raw.chunks(stride + 1) {
+        if row[0] != 0 {
+            return Err(unsupported());
+        }
+        rgba.extend_from_slice(&row[1..]);
+    }
+    Ok((width, height, rgba))
+}
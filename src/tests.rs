@@ -18,11 +18,27 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use std::fs::File;
-use std::io::Read;
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
 use std::error;
+use std::ptr;
+use std::sync::Arc;
+use std::thread;
 
-use super::{CrnFormat, CrunchedData};
+use super::{
+    analysis, block, crunch, cube, format, header, is_crn, pixels, remap_faces, sys, testing, tile,
+    CancelToken, ChannelOrder, ColorSpace, CompatWarning, CrnFormat, CrnHeader, CrunchedArray,
+    CrunchedData, CrunchedDataOwned, CrunchedReader, CubeConvention, CubeLayout, DecodedLevel,
+    DecodedTexture, DecoderOptions, DecrunchError, Diagnostic, Endian, FaceOrientation, Finding,
+    Inconsistency, LevelInfo, Levels, ParseFormatError, PixelFormat, Progress, ScratchAllocator,
+    StableBytes, TextureLayout, TextureLibrary, Tile, TruncationLoss, CRN_MAGIC,
+    CRN_MIN_HEADER_SIZE, DEFAULT_MAX_OUTPUT_SIZE,
+};
+use testing::approval::{self, Approval, ApprovalError, Outcome};
+use unity::{Bundle, GraphicsFormat};
 
 #[test]
 fn decompress_dxt1() {
@@ -98,3 +114,2138 @@ fn decompress_dxt1() {
     assert_eq!(decompressed_data.len(), correct_decompressed_data.len());
     assert_eq!(decompressed_data, correct_decompressed_data);
 }
+
+#[test]
+fn reference_vector_roundtrip() {
+    let v = testing::ReferenceVector {
+        name: "copyright_2048".to_string(),
+        level: 3,
+        len: 32768,
+        hash: testing::hash(b"crunch"),
+        tile: vec![0x00, 0x7f, 0xff, 0x10],
+    };
+
+    let line = v.to_line();
+    assert_eq!(testing::ReferenceVector::from_line(&line), Some(v.clone()));
+
+    let mut out = Vec::new();
+    testing::write_vectors(&mut out, std::slice::from_ref(&v)).unwrap();
+    let read = testing::read_vectors(&out[..]).unwrap();
+    assert_eq!(read, vec![v]);
+
+    assert_eq!(testing::ReferenceVector::from_line("bad 1 2 zz 00"), None);
+
+    let spaced = testing::ReferenceVector {
+        name: "my texture\t100%".to_string(),
+        ..read[0].clone()
+    };
+    let line = spaced.to_line();
+    assert!(line.starts_with("my%20texture%09100%25 "));
+    assert_eq!(testing::ReferenceVector::from_line(&line), Some(spaced));
+    assert_eq!(testing::ReferenceVector::from_line("bad%2 1 2 00 00"), None);
+}
+
+#[test]
+fn strict_rejects_invalid_header() {
+    let garbage = vec![0u8; 128];
+    let c_data = CrunchedData::new(&garbage);
+    assert_eq!(c_data.check_level(0), Err(Inconsistency::Header));
+    assert_eq!(c_data.decode_level_strict(0), Err(Inconsistency::Header));
+}
+
+#[test]
+fn strict_bounds_levels_by_dimensions() {
+    // 64x64 allows 7 levels, far fewer than the header declares.
+    let data = synthetic_crn(64, 64, 200, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    let out_of_range = Inconsistency::LevelOutOfRange {
+        level: 40,
+        levels: 7,
+    };
+    assert_eq!(c_data.check_level(40), Err(out_of_range.clone()));
+    assert_eq!(c_data.decode_level_strict(40), Err(out_of_range));
+}
+
+/// Builds a CRN header followed by `levels` chunks of 16 zero bytes. The
+/// result parses but does not decode.
+fn synthetic_crn(width: u16, height: u16, levels: u8, format: u8, flags: u16) -> Vec<u8> {
+    let header_size = 74 + 4 * (u32::from(levels) - 1);
+    let data_size = header_size + 16 * u32::from(levels);
+
+    let mut data = Vec::new();
+    data.extend_from_slice(b"Hx");
+    data.extend_from_slice(&(header_size as u16).to_be_bytes());
+    data.extend_from_slice(&[0, 0]);
+    data.extend_from_slice(&data_size.to_be_bytes());
+    data.extend_from_slice(&[0, 0]);
+    data.extend_from_slice(&width.to_be_bytes());
+    data.extend_from_slice(&height.to_be_bytes());
+    data.extend_from_slice(&[levels, 1, format]);
+    data.extend_from_slice(&flags.to_be_bytes());
+    data.resize(70, 0);
+    for level in 0..u32::from(levels) {
+        data.extend_from_slice(&(header_size + 16 * level).to_be_bytes());
+    }
+    data.resize(data_size as usize, 0);
+
+    let data_crc = header::crc16(&data[header_size as usize..]);
+    data[10..12].copy_from_slice(&data_crc.to_be_bytes());
+    let header_crc = header::crc16(&data[6..header_size as usize]);
+    data[4..6].copy_from_slice(&header_crc.to_be_bytes());
+    data
+}
+
+#[test]
+fn compat_warnings() {
+    let data = synthetic_crn(64, 64, 1, 0, 0);
+    assert_eq!(CrunchedData::new(&data).compat_warnings(), Some(vec![]));
+
+    let data = synthetic_crn(64, 64, 2, 20, 0x0003);
+    assert_eq!(
+        CrunchedData::new(&data).compat_warnings(),
+        Some(vec![
+            CompatWarning::UnknownFormat(20),
+            CompatWarning::Segmented,
+            CompatWarning::UnknownFlags(0x0002),
+        ])
+    );
+
+    assert_eq!(CrunchedData::new(&[0u8; 16]).compat_warnings(), None);
+}
+
+#[test]
+fn payload_layout() {
+    let data = synthetic_crn(64, 32, 3, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    let layout = c_data.payload_layout().unwrap();
+
+    assert_eq!(layout.header_size, 82);
+    assert_eq!(layout.data_size, 130);
+    assert_eq!(layout.tables_size(), 0);
+    assert_eq!(layout.levels, vec![82..98, 98..114, 114..130]);
+    assert_eq!(layout.levels_size(), 48);
+    assert_eq!(c_data.level_range(1), Some(98..114));
+    assert_eq!(c_data.level_range(3), None);
+}
+
+#[test]
+fn diagnostics() {
+    let mut data = synthetic_crn(64, 64, 2, 0, 0x0010);
+    data.extend_from_slice(&[0xAA; 5]);
+    let c_data = CrunchedData::new(&data);
+
+    assert_eq!(
+        c_data.diagnostics(),
+        vec![
+            Diagnostic::Compat(CompatWarning::UnknownFlags(0x0010)),
+            Diagnostic::TrailingBytes(5),
+        ]
+    );
+    assert_eq!(
+        CrunchedData::new(&[0u8; 16]).diagnostics(),
+        vec![Diagnostic::InvalidHeader]
+    );
+
+    // A level count beyond crunch's limit is reported, not a panic.
+    let data = synthetic_crn(64, 64, 200, 0, 0);
+    let diagnostics = CrunchedData::new(&data).diagnostics();
+    // Levels 7 to 15 are past a 64x64 mip chain; later ones are not listed.
+    assert_eq!(diagnostics.len(), 9);
+    assert_eq!(
+        diagnostics[8],
+        Diagnostic::Level {
+            level: 15,
+            issue: Inconsistency::LevelOutOfRange {
+                level: 15,
+                levels: 7
+            }
+        }
+    );
+}
+
+#[test]
+fn crunched_array_slices() {
+    let first = synthetic_crn(64, 64, 1, 0, 0);
+    let second = synthetic_crn(32, 16, 2, 0, 0);
+    let mut data = first.clone();
+    data.extend_from_slice(&second);
+
+    let array = CrunchedArray::new(&data, 2).unwrap();
+    assert_eq!(array.len(), 2);
+    assert_eq!(array.get(0).unwrap().buffer, &first[..]);
+    assert_eq!(array.get(1).unwrap().texture_info().width, 32);
+    assert_eq!(array.iter().count(), 2);
+
+    assert!(CrunchedArray::new(&data, 3).is_none());
+
+    // A data size smaller than the header itself is invalid, not an empty
+    // slice.
+    let mut empty = first.clone();
+    empty[6..10].copy_from_slice(&[0; 4]);
+    let data = [&empty[..], &empty[..], &empty[..]].concat();
+    assert!(CrunchedArray::new(&data, 3).is_none());
+}
+
+#[test]
+fn tile_copy_clamps_to_edges() {
+    // 3x2 level of 1-byte blocks numbered row by row.
+    let info = LevelInfo {
+        width: 12,
+        height: 8,
+        faces: 1,
+        blocks_x: 3,
+        blocks_y: 2,
+        bytes_per_block: 1,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let decoded = [0, 1, 2, 3, 4, 5];
+
+    let tile = Tile::square(4, 0, 4, 0);
+    assert_eq!(tile::copy_tile(&decoded, &info, &tile), vec![1]);
+
+    let tile = Tile::square(0, 0, 4, 4);
+    assert_eq!(
+        tile::copy_tile(&decoded, &info, &tile),
+        vec![0, 0, 1, 0, 0, 1, 3, 3, 4]
+    );
+}
+
+#[test]
+fn tiles_outside_the_level_are_rejected() {
+    let info = LevelInfo {
+        width: 12,
+        height: 8,
+        faces: 1,
+        blocks_x: 3,
+        blocks_y: 2,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let check = |tile: Tile| tile::check_tile(&info, &tile, 1 << 20);
+
+    assert_eq!(check(Tile::square(8, 4, 4, 4)), Ok(()));
+    for &tile in &[
+        Tile::square(2, 0, 4, 0),
+        Tile::square(12, 0, 4, 0),
+        Tile::square(8, 4, 8, 0),
+        Tile::square(u32::MAX - 3, 0, 4, 0),
+    ] {
+        assert_eq!(check(tile), Err(DecrunchError::InvalidTile(tile)));
+    }
+    assert_eq!(
+        check(Tile::square(0, 0, 4, 1 << 12)),
+        Err(DecrunchError::OutputTooLarge {
+            size: 2049 * 2049 * 8,
+            limit: 1 << 20
+        })
+    );
+}
+
+#[test]
+fn level_cache_misses_on_undecodable_data() {
+    let data = synthetic_crn(64, 64, 1, 0, 0);
+    let mut c_data = CrunchedData::new(&data);
+
+    assert_eq!(c_data.cache_level(0), None);
+    assert_eq!(c_data.cached_level(0), None);
+    assert!(!c_data.evict_level(0));
+}
+
+struct CountingArena {
+    blocks: RefCell<Vec<Vec<u64>>>,
+}
+
+unsafe impl ScratchAllocator for CountingArena {
+    fn alloc(&self, size: usize, align: usize) -> *mut u8 {
+        assert!(align <= 16);
+        let mut block = vec![0u64; size.div_ceil(8) + 1];
+        let p = block.as_mut_ptr() as usize;
+        let p = (p + align - 1) & !(align - 1);
+        self.blocks.borrow_mut().push(block);
+        p as *mut u8
+    }
+}
+
+#[test]
+fn scratch_allocator_serves_crunch_allocations() {
+    let data = synthetic_crn(64, 64, 1, 0, 0);
+    let arena = CountingArena {
+        blocks: RefCell::new(Vec::new()),
+    };
+    {
+        let c_data = CrunchedData::new_in(&data, &arena);
+        let _ = c_data.decode_level(0);
+    }
+    assert!(!arena.blocks.borrow().is_empty());
+}
+
+#[test]
+fn forensic_report_lists_every_bad_field() {
+    let mut data = synthetic_crn(64, 64, 2, 0, 0);
+    let fields = |data: &[u8]| -> Vec<&'static str> {
+        CrunchedData::new(data)
+            .forensic_report()
+            .findings
+            .iter()
+            .map(|f| f.field)
+            .collect()
+    };
+    assert_eq!(fields(&data), vec!["m_color_endpoints", "m_tables_size"]);
+
+    data[18] = 40;
+    data[80] = 0xFF;
+    let report = CrunchedData::new(&data).forensic_report();
+    assert_eq!(
+        report.findings[0],
+        Finding {
+            field: "m_header_crc16",
+            offset: 4,
+            expected: format!("{:#06x}", header::crc16(&data[6..78])),
+            actual: format!("{:#06x}", u16::from_be_bytes([data[4], data[5]])),
+        }
+    );
+    assert_eq!(
+        fields(&data),
+        vec![
+            "m_header_crc16",
+            "m_data_crc16",
+            "m_format",
+            "m_color_endpoints",
+            "m_tables_size"
+        ]
+    );
+
+    assert_eq!(fields(&[b'x'; 100]), vec!["m_sig"]);
+    assert_eq!(fields(&data[..40]), vec!["file"]);
+}
+
+#[test]
+fn truncation_loss_display() {
+    let loss = TruncationLoss {
+        expected_size: 4096,
+        actual_size: 1000,
+        lost_levels: 3..6,
+    };
+    assert_eq!(
+        loss.to_string(),
+        "1000 of 4096 bytes present, levels 3 to 5 lost"
+    );
+
+    let data = synthetic_crn(64, 64, 3, 0, 0);
+    assert_eq!(CrunchedData::decode_truncated(&data[..80]), None);
+
+    // Levels starting inside the header or running backwards.
+    let mut garbage = data.clone();
+    garbage[70..78].copy_from_slice(&[0, 0, 0, 0, 0, 0, 0, 10]);
+    assert_eq!(CrunchedData::decode_truncated(&garbage[..100]), None);
+    let mut garbage = data.clone();
+    garbage[77] = 200;
+    assert_eq!(CrunchedData::decode_truncated(&garbage), None);
+}
+
+#[test]
+fn alpha_coverage_from_dxt5_blocks() {
+    // Two DXT5 blocks: the first fully opaque, the second with alpha
+    // endpoints 0/255 and every index 0 (alpha 0).
+    let mut decoded = vec![0u8; 32];
+    decoded[0] = 255;
+    decoded[1] = 255;
+    decoded[17] = 255;
+    let info = LevelInfo {
+        width: 6,
+        height: 4,
+        faces: 1,
+        blocks_x: 2,
+        blocks_y: 1,
+        bytes_per_block: 16,
+        format: CrnFormat::Dxt5,
+        ..LevelInfo::default()
+    };
+    assert_eq!(
+        analysis::alpha_coverage(&info, &decoded, 127),
+        Some(16.0 / 24.0)
+    );
+
+    let info = LevelInfo {
+        format: CrnFormat::DxNXy,
+        ..info
+    };
+    assert_eq!(analysis::alpha_coverage(&info, &decoded, 127), None);
+}
+
+#[test]
+fn dxt_block_alpha() {
+    // Three-color DXT1 block (c0 <= c1) with texel 0 using index 3.
+    assert_eq!(block::dxt1_alpha(&[0, 0, 1, 0, 3, 0, 0, 0])[..2], [0, 255]);
+    assert_eq!(
+        block::dxt3_alpha(&[0x1F, 0, 0, 0, 0, 0, 0, 0])[..3],
+        [255, 17, 0]
+    );
+    // Six-value DXT5 block with texel indices 0, 6 and 3.
+    assert_eq!(
+        block::dxt5_alpha(&[10, 20, 0b11_110_000, 0, 0, 0, 0, 0])[..3],
+        [10, 0, 14]
+    );
+}
+
+#[test]
+fn histogram_of_dxt1_blocks() {
+    // Block 0: solid red (c0 = c1 = 0xF800, all indices 0). Block 1: solid
+    // blue, with a transparent texel 15 (three-color mode, index 3).
+    let decoded = [
+        0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0, //
+        0x1F, 0x00, 0x1F, 0x00, 0, 0, 0, 0xC0,
+    ];
+    let info = LevelInfo {
+        width: 8,
+        height: 4,
+        faces: 1,
+        blocks_x: 2,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let histogram = analysis::histogram(&info, &decoded).unwrap();
+
+    assert_eq!(histogram.texels, 32);
+    assert_eq!(histogram.channels[0][255], 16);
+    assert_eq!(histogram.channels[3][0], 1);
+    assert_eq!(histogram.average_color(), [128, 0, 120, 247]);
+
+    let dominant = histogram.dominant_colors(2);
+    assert_eq!(dominant[0].rgba, [255, 0, 0, 255]);
+    assert_eq!(dominant[0].share, 0.5);
+    assert_eq!(dominant[1].rgba, [0, 0, 255, 255]);
+}
+
+#[test]
+fn palette_average_color_blends_endpoints() {
+    // Red/blue endpoint pair, selectors all 0 (red) or all 1 (blue), and
+    // alpha endpoints 0 and 255.
+    let endpoints = [0x001F_F800];
+    let alpha = [0xFF00];
+    let average = |selectors: &[u32]| {
+        analysis::palette_average_color(CrnFormat::Dxt5, &endpoints, selectors, &alpha)
+    };
+
+    assert_eq!(average(&[0]), Some([255, 0, 0, 128]));
+    assert_eq!(average(&[0, 0x5555_5555]), Some([128, 0, 128, 128]));
+    assert_eq!(
+        analysis::palette_average_color(CrnFormat::DxNXy, &endpoints, &[0], &alpha),
+        None
+    );
+}
+
+#[test]
+fn planes_of_partial_dxt1_block() {
+    // A 3x2 level in one solid red block with a transparent texel at (1, 0).
+    let decoded = [0x00, 0xF8, 0x00, 0xF8, 0x0C, 0, 0, 0];
+    let info = LevelInfo {
+        width: 3,
+        height: 2,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let planes = pixels::planes(&info, &decoded, None).unwrap();
+
+    assert_eq!(planes.r, [255, 0, 255, 255, 255, 255]);
+    assert_eq!(planes.b, [0; 6]);
+    assert_eq!(planes.a, [255, 0, 255, 255, 255, 255]);
+}
+
+#[test]
+fn ccxy_texels_convert_to_rgb() {
+    assert_eq!(block::ccxy_to_rgb([123, 125, 0, 128]), [128, 128, 128, 255]);
+    assert_eq!(block::ccxy_to_rgb([198, 113, 0, 112]), [95, 95, 245, 255]);
+    assert_eq!(block::ccxy_to_rgb([255, 0, 0, 255]), [80, 255, 255, 255]);
+}
+
+#[test]
+fn ccxy_level_transcodes_to_rgb() {
+    // One DXT5 block: luma 112 in alpha, Cb + 123 = 198 and Cr + 125 = 113.
+    let decoded = [112, 112, 0, 0, 0, 0, 0, 0, 0x80, 0xC3, 0x80, 0xC3, 0, 0, 0, 0];
+    let info = LevelInfo {
+        width: 1,
+        height: 1,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 16,
+        format: CrnFormat::Dxt5cCxY,
+        ..LevelInfo::default()
+    };
+    assert_eq!(
+        pixels::transcode(&info, &decoded, &DecoderOptions::new()).unwrap(),
+        [95, 95, 245, 255]
+    );
+}
+
+#[test]
+fn crunched_reader_reads_only_the_base() {
+    // Point the tables at the first 8 bytes after the header.
+    let mut data = synthetic_crn(64, 64, 2, 0, 0);
+    data[65..67].copy_from_slice(&8u16.to_be_bytes());
+    data[67..70].copy_from_slice(&[0, 0, 78]);
+    let header = header::Header::parse_prefix(&data[..78]).unwrap();
+    assert_eq!(header.base_size(), 86);
+
+    let kind = |data: &[u8]| match CrunchedReader::new(Cursor::new(data)) {
+        Ok(_) => None,
+        Err(err) => Some(err.kind()),
+    };
+    assert_eq!(kind(&data[..70]), Some(io::ErrorKind::UnexpectedEof));
+    assert_eq!(kind(&data[..80]), Some(io::ErrorKind::UnexpectedEof));
+    // All bytes the base needs are there, but the zeroed tables don't decode.
+    assert_eq!(kind(&data[..86]), Some(io::ErrorKind::InvalidData));
+    assert_eq!(kind(&[0; 100]), Some(io::ErrorKind::InvalidData));
+}
+
+#[test]
+fn unity_graphics_formats() {
+    let format = GraphicsFormat::from_raw(119).unwrap();
+    assert_eq!(format, GraphicsFormat::RgbEtc2Srgb);
+    assert!(format.is_srgb());
+    assert!(format.accepts(CrnFormat::Etc1));
+    assert!(!format.accepts(CrnFormat::Etc2A));
+
+    assert!(!GraphicsFormat::RgbaDxt5UNorm.is_srgb());
+    assert_eq!(
+        GraphicsFormat::RgbaDxt5UNorm.crn_formats(),
+        [CrnFormat::Dxt5]
+    );
+    assert_eq!(GraphicsFormat::from_raw(98), None);
+}
+
+#[test]
+fn texture_library_indexes_crn_files() {
+    let dir = env::temp_dir().join(format!("decrunch-library-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.crn"), synthetic_crn(64, 32, 1, 0, 0)).unwrap();
+    fs::write(dir.join("b.dat"), synthetic_crn(16, 16, 2, 2, 0)).unwrap();
+    fs::write(dir.join("notes.txt"), b"not a texture").unwrap();
+
+    let mut library = TextureLibrary::open(&dir).unwrap();
+    assert_eq!(library.names().collect::<Vec<_>>(), ["a.crn", "b.dat"]);
+    assert!(library.errors().is_empty());
+    let info = library.texture_info("b.dat").unwrap();
+    assert_eq!(
+        (info.width, info.levels, info.format),
+        (16, 2, CrnFormat::Dxt5)
+    );
+
+    let mut kind = |name| library.decode_level(name, 0).unwrap_err().kind();
+    assert_eq!(kind("a.crn"), io::ErrorKind::InvalidData);
+    assert_eq!(kind("missing.crn"), io::ErrorKind::NotFound);
+    assert!(!library.unload("a.crn"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unityfs_bundle_nodes() {
+    // "abc" then a 9 byte match at offset 3, then "!".
+    let lz4_block = [0x35, b'a', b'b', b'c', 3, 0, 0x10, b'!'];
+
+    let mut info = vec![0; 16];
+    info.extend_from_slice(&2u32.to_be_bytes());
+    info.extend_from_slice(&[0, 0, 0, 13, 0, 0, 0, 8, 0, 2]);
+    info.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 3, 0, 0]);
+    info.extend_from_slice(&2u32.to_be_bytes());
+    for &(offset, size, name) in &[(0u64, 10u64, "CAB-a"), (10, 6, "CAB-b")] {
+        info.extend_from_slice(&offset.to_be_bytes());
+        info.extend_from_slice(&size.to_be_bytes());
+        info.extend_from_slice(&4u32.to_be_bytes());
+        info.extend_from_slice(name.as_bytes());
+        info.push(0);
+    }
+
+    let mut bundle = b"UnityFS\0".to_vec();
+    bundle.extend_from_slice(&6u32.to_be_bytes());
+    bundle.extend_from_slice(b"5.x.x\x002019.4.40f1\0");
+    bundle.extend_from_slice(&0u64.to_be_bytes());
+    bundle.extend_from_slice(&(info.len() as u32).to_be_bytes());
+    bundle.extend_from_slice(&(info.len() as u32).to_be_bytes());
+    bundle.extend_from_slice(&0x40u32.to_be_bytes());
+    bundle.extend_from_slice(&info);
+    bundle.extend_from_slice(&lz4_block);
+    bundle.extend_from_slice(b"xyz");
+
+    // A directory size past the end of the data fails without allocating it.
+    let mut huge = bundle.clone();
+    huge[38..42].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+    let err = Bundle::open(Cursor::new(huge)).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let mut bundle = Bundle::open(Cursor::new(bundle)).unwrap();
+    assert_eq!(bundle.unity_version(), "2019.4.40f1");
+    assert_eq!(bundle.nodes().len(), 2);
+    assert_eq!(bundle.nodes()[1].path, "CAB-b");
+    assert_eq!(bundle.read_node(0).unwrap(), b"abcabcabca");
+    assert_eq!(bundle.read_node(1).unwrap(), b"bc!xyz");
+    assert!(bundle.read_node(2).is_err());
+}
+
+#[test]
+fn approval_snapshots() {
+    let dir = env::temp_dir().join(format!("decrunch-approval-{}", std::process::id()));
+    let rgba: Vec<u8> = (0..3 * 2 * 4).map(|i| (i * 10) as u8).collect();
+
+    let mut png = Vec::new();
+    approval::write_png(&mut png, 3, 2, &rgba).unwrap();
+    assert_eq!(approval::read_png(&png[..]).unwrap(), (3, 2, rgba.clone()));
+    // The IDAT data follows the signature, IHDR and the IDAT length and
+    // type. A fixed Huffman block is rejected even with a valid CRC, and so
+    // is any corrupted byte.
+    let idat = 8 + 25 + 8;
+    let idat_len = png.len() - idat - 4 - 12;
+    let mut fixed = png.clone();
+    fixed[idat + 2] = 0x03;
+    let crc = approval::crc32(&[b"IDAT", &fixed[idat..idat + idat_len]]);
+    fixed[idat + idat_len..idat + idat_len + 4].copy_from_slice(&crc.to_be_bytes());
+    let mut corrupt = png.clone();
+    corrupt[idat + 10] ^= 1;
+    // Dimensions whose pixel count overflows are rejected, not a panic.
+    let mut huge = png.clone();
+    huge[16..24].copy_from_slice(&[0xFF; 8]);
+    let crc = approval::crc32(&[b"IHDR", &huge[16..29]]);
+    huge[29..33].copy_from_slice(&crc.to_be_bytes());
+    for png in &[fixed, corrupt, huge] {
+        let err = approval::read_png(&png[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    let approval = Approval::new(&dir);
+    assert_eq!(
+        approval.check_rgba("t", 3, 2, &rgba).unwrap(),
+        Outcome::Recorded
+    );
+    assert_eq!(
+        approval.check_rgba("t", 3, 2, &rgba).unwrap(),
+        Outcome::Matched
+    );
+
+    let mut changed = rgba.clone();
+    changed[17] += 2;
+    match approval.check_rgba("t", 3, 2, &changed) {
+        Err(ApprovalError::Pixels {
+            differing, first, ..
+        }) => assert_eq!((differing, first), (1, (1, 1))),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(dir.join("t.actual.png").exists());
+    let lenient = approval.with_tolerance(2);
+    assert_eq!(
+        lenient.check_rgba("t", 3, 2, &changed).unwrap(),
+        Outcome::Matched
+    );
+    assert!(!dir.join("t.actual.png").exists());
+    match lenient.check_rgba("t", 3, 3, &changed) {
+        Err(ApprovalError::Length { expected, actual }) => assert_eq!((expected, actual), (36, 24)),
+        other => panic!("unexpected {:?}", other),
+    }
+    match lenient.check_rgba("t", 2, 3, &changed) {
+        Err(ApprovalError::Size { expected, .. }) => assert_eq!(expected, (3, 2)),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rgba_pipeline_fails_on_undecodable_levels() {
+    let data = synthetic_crn(16, 16, 3, 0, 0);
+    assert_eq!(CrunchedData::new(&data).decode_levels_to_rgba(), None);
+    let data = synthetic_crn(16, 16, 3, 7, 0);
+    assert_eq!(CrunchedData::new(&data).decode_levels_to_rgba(), None);
+}
+
+#[test]
+fn typed_formats() {
+    use format::Format;
+
+    assert_eq!(format::Dxt1::BLOCK_SIZE, 8);
+    assert_eq!(format::Dxt5::FORMAT, CrnFormat::Dxt5);
+    let red = format::Dxt1::block(&[0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0]);
+    assert_eq!(format::Dxt1::texels(&red)[15], [255, 0, 0, 255]);
+
+    let data = synthetic_crn(16, 16, 1, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(c_data.decode_typed::<format::Dxt5>(0), None);
+}
+
+#[test]
+fn linear_output_applies_srgb_eotf() {
+    assert_eq!(pixels::srgb_to_linear(0), 0.0);
+    assert_eq!(pixels::srgb_to_linear(255), 1.0);
+    assert!((pixels::srgb_to_linear(128) - 0.2159).abs() < 1e-4);
+
+    // One DXT1 block, solid 5:6:5 gray (132, 130, 132).
+    let decoded = [0x10, 0x84, 0x10, 0x84, 0, 0, 0, 0];
+    let info = LevelInfo {
+        width: 4,
+        height: 4,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let linear = pixels::linear(&info, &decoded, ColorSpace::Srgb, None).unwrap();
+    assert_eq!(linear.len(), 64);
+    assert_eq!(linear[0], pixels::srgb_to_linear(132));
+    assert_eq!(linear[1], pixels::srgb_to_linear(130));
+    assert_eq!(linear[3], 1.0);
+}
+
+#[test]
+fn packed_pixels() {
+    // One DXT1 block, solid red.
+    let decoded = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+    let info = LevelInfo {
+        width: 4,
+        height: 4,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let packed = |order, endian| pixels::packed(&info, &decoded, order, endian, None).unwrap()[0];
+
+    assert_eq!(packed(ChannelOrder::Argb, Endian::Host), 0xFFFF_0000);
+    assert_eq!(packed(ChannelOrder::Rgba, Endian::Host), 0xFF00_00FF);
+    assert_eq!(packed(ChannelOrder::Bgra, Endian::Host), 0x0000_FFFF);
+    assert_eq!(
+        packed(ChannelOrder::Argb, Endian::Big).to_ne_bytes(),
+        [255, 255, 0, 0]
+    );
+    assert_eq!(
+        packed(ChannelOrder::Argb, Endian::Little).to_ne_bytes(),
+        [0, 0, 255, 255]
+    );
+}
+
+#[test]
+fn level_block_iterator() {
+    let info = LevelInfo {
+        width: 12,
+        height: 8,
+        faces: 1,
+        blocks_x: 3,
+        blocks_y: 2,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let decoded: Vec<u8> = (0..48).collect();
+    let blocks: Vec<_> = info.blocks(&decoded).collect();
+    assert_eq!(blocks.len(), 6);
+    assert_eq!(blocks[4], (1, 1, &decoded[32..40]));
+    assert_eq!(info.blocks(&decoded[..20]).len(), 2);
+}
+
+#[test]
+fn cube_faces_flip_between_conventions() {
+    // 1x2 faces of one byte per pixel: top row i, bottom row i + 10.
+    let faces: Vec<Vec<u8>> = (0..6).map(|i| vec![i, i + 10]).collect();
+
+    let same = remap_faces(&faces, 1, 2, CubeConvention::OpenGl, CubeConvention::Vulkan);
+    assert_eq!(same.unwrap(), faces);
+    let flipped = remap_faces(
+        &faces,
+        1,
+        2,
+        CubeConvention::Unity,
+        CubeConvention::Direct3D,
+    );
+    assert_eq!(flipped.unwrap()[3], [13, 3]);
+    assert_eq!(
+        remap_faces(
+            &faces[..5],
+            1,
+            2,
+            CubeConvention::Unity,
+            CubeConvention::Vulkan
+        ),
+        None
+    );
+
+    // Babylon.js takes the positive faces first.
+    let babylon = remap_faces(
+        &faces,
+        1,
+        2,
+        CubeConvention::Vulkan,
+        CubeConvention::Babylon,
+    );
+    let order: Vec<u8> = babylon.as_ref().unwrap().iter().map(|f| f[0]).collect();
+    assert_eq!(order, [0, 2, 4, 1, 3, 5]);
+    let back = remap_faces(
+        &babylon.unwrap(),
+        1,
+        2,
+        CubeConvention::Babylon,
+        CubeConvention::Direct3D,
+    );
+    assert_eq!(back.unwrap(), faces);
+}
+
+#[test]
+fn cube_faces_turn_with_custom_layouts() {
+    use FaceOrientation::*;
+
+    assert_eq!(CubeLayout::new([(0, Upright); 6]), None);
+    let layout = CubeLayout::new([
+        (5, Upright),
+        (1, Rotated90),
+        (2, Rotated180),
+        (3, Rotated270),
+        (4, FlippedX),
+        (0, FlippedY),
+    ])
+    .unwrap();
+
+    // 2x2 faces of one byte per pixel, 10 * face + row-major index.
+    let faces: Vec<Vec<u8>> = (0..6)
+        .map(|i| (0..4).map(|p| 10 * i + p).collect())
+        .collect();
+    let custom = CubeConvention::Custom(layout);
+    let turned = remap_faces(&faces, 2, 2, CubeConvention::Direct3D, custom).unwrap();
+    assert_eq!(
+        turned,
+        [
+            vec![50, 51, 52, 53],
+            vec![12, 10, 13, 11],
+            vec![23, 22, 21, 20],
+            vec![31, 33, 30, 32],
+            vec![41, 40, 43, 42],
+            vec![2, 3, 0, 1],
+        ]
+    );
+    let back = remap_faces(&turned, 2, 2, custom, CubeConvention::Direct3D);
+    assert_eq!(back.unwrap(), faces);
+    assert_eq!(
+        remap_faces(&faces, 1, 4, CubeConvention::Direct3D, custom),
+        None
+    );
+}
+
+#[test]
+fn cube_convention_applies_to_blocks() {
+    // 8x8 DXT1 faces of 2x2 blocks. Each block has its face and block number
+    // in the first endpoint and the texel's row in its indices.
+    let info = LevelInfo {
+        width: 8,
+        height: 8,
+        faces: 6,
+        blocks_x: 2,
+        blocks_y: 2,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let mut level = Vec::new();
+    for face in 0..6 {
+        for block in 0..4 {
+            level.extend_from_slice(&[face * 4 + block, 0, 0, 0, 0x00, 0x55, 0xAA, 0xFF]);
+        }
+    }
+    let original = level.clone();
+
+    let mut babylon = level.clone();
+    cube::orient_level(&info, &mut babylon, 16, CubeConvention::Babylon).unwrap();
+    let order: Vec<u8> = babylon.chunks(32).map(|face| face[0] / 4).collect();
+    assert_eq!(order, [0, 2, 4, 1, 3, 5]);
+
+    // Unity stores rows bottom up: block rows swap and texel rows reverse.
+    cube::orient_level(&info, &mut level, 16, CubeConvention::Unity).unwrap();
+    assert_eq!(level[..8], [2, 0, 0, 0, 0xFF, 0xAA, 0x55, 0x00]);
+    assert_eq!(level[16], 0);
+
+    // A quarter turn makes the row indices column indices.
+    let turn = CubeLayout::new([
+        (0, FaceOrientation::Rotated90),
+        (1, FaceOrientation::Upright),
+        (2, FaceOrientation::Upright),
+        (3, FaceOrientation::Upright),
+        (4, FaceOrientation::Upright),
+        (5, FaceOrientation::Upright),
+    ])
+    .unwrap();
+    let mut turned = original.clone();
+    cube::orient_level(&info, &mut turned, 16, CubeConvention::Custom(turn)).unwrap();
+    assert_eq!(turned[..8], [2, 0, 0, 0, 0x1B, 0x1B, 0x1B, 0x1B]);
+    assert_eq!(turned[8], 0);
+    assert_eq!(turned[32..], original[32..]);
+
+    let etc = LevelInfo {
+        format: CrnFormat::Etc1,
+        ..info
+    };
+    assert_eq!(
+        cube::orient_level(&etc, &mut level, 16, CubeConvention::Unity),
+        Err(DecrunchError::FlipUnsupported(CrnFormat::Etc1))
+    );
+    cube::orient_level(&etc, &mut babylon, 16, CubeConvention::Babylon).unwrap();
+}
+
+#[test]
+fn decode_errors() {
+    let garbage = vec![0u8; 128];
+    let c_data = CrunchedData::new(&garbage);
+    assert_eq!(c_data.try_texture_info(), Err(DecrunchError::InvalidHeader));
+    assert_eq!(
+        c_data.try_decode_level(0),
+        Err(DecrunchError::InvalidHeader)
+    );
+
+    let data = synthetic_crn(16, 16, 2, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(c_data.try_level_info(1).unwrap().width, 8);
+    assert_eq!(
+        c_data.try_decode_level(2),
+        Err(DecrunchError::LevelOutOfRange {
+            level: 2,
+            levels: 2
+        })
+    );
+    assert_eq!(
+        c_data.try_decode_level(0),
+        Err(DecrunchError::CorruptTables)
+    );
+
+    let data = synthetic_crn(16, 16, 1, 20, 0);
+    let err = CrunchedData::new(&data).try_texture_info().unwrap_err();
+    assert_eq!(err, DecrunchError::UnsupportedFormat(20));
+    assert_eq!(
+        err.to_string(),
+        "format 20 is not supported by decoder 01.04"
+    );
+}
+
+#[test]
+fn try_new_validates_header() {
+    let kind = |data: &[u8]| CrunchedData::try_new(data).err();
+    assert_eq!(kind(b"Hx"), Some(DecrunchError::InvalidHeader));
+    assert_eq!(kind(&[0; 128]), Some(DecrunchError::InvalidHeader));
+    assert_eq!(
+        kind(&synthetic_crn(16, 16, 1, 20, 0)),
+        Some(DecrunchError::UnsupportedFormat(20))
+    );
+    assert_eq!(
+        kind(&synthetic_crn(16, 16, 1, 0, 0)),
+        Some(DecrunchError::CorruptTables)
+    );
+}
+
+#[test]
+fn decode_level_into_checks_destination() {
+    let data = synthetic_crn(16, 16, 2, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    let mut dst = [0; 16];
+    assert_eq!(
+        c_data.decode_level_into(0, &mut dst),
+        Err(DecrunchError::BufferTooSmall {
+            needed: 128,
+            actual: 16
+        })
+    );
+    assert_eq!(
+        c_data.decode_level_into(1, &mut [0; 64]),
+        Err(DecrunchError::CorruptTables)
+    );
+}
+
+#[test]
+fn owned_data_moves_across_threads() {
+    let owned = CrunchedDataOwned::new(synthetic_crn(32, 16, 3, 0, 0));
+    let (levels, width) = thread::spawn(move || {
+        let info = owned.data().try_texture_info().unwrap();
+        (info.levels, owned.data().try_level_info(1).unwrap().width)
+    })
+    .join()
+    .unwrap();
+    assert_eq!((levels, width), (3, 16));
+    assert!(CrunchedDataOwned::try_new(b"Hx".to_vec()).is_err());
+}
+
+#[test]
+fn shared_data_decodes_on_workers() {
+    let bytes: Arc<[u8]> = synthetic_crn(64, 64, 4, 0, 0).into();
+    let workers: Vec<_> = (0..4)
+        .map(|level| {
+            let owned = CrunchedDataOwned::from_shared(bytes.clone());
+            thread::spawn(move || owned.data().try_level_info(level).unwrap().width)
+        })
+        .collect();
+    let widths: Vec<u32> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+    assert_eq!(widths, [64, 32, 16, 8]);
+}
+
+#[test]
+fn owned_data_from_storage() {
+    struct Network(Box<[u8]>);
+
+    impl AsRef<[u8]> for Network {
+        fn as_ref(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    // The bytes live on the heap and are never changed.
+    unsafe impl StableBytes for Network {}
+
+    let data = synthetic_crn(16, 8, 1, 0, 0);
+    let ptr = data.as_ptr();
+    let owned = CrunchedDataOwned::from_storage(Network(data.into_boxed_slice()));
+    assert_eq!(owned.as_bytes().as_ptr(), ptr);
+    assert_eq!(owned.data().try_level_info(0).unwrap().height, 8);
+}
+
+#[test]
+fn levels_iterates_mip_chain() {
+    let data = synthetic_crn(64, 32, 4, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    let levels: Levels = c_data.levels();
+    assert_eq!(levels.len(), 4);
+    let sizes: Vec<_> = levels.map(|l| (l.width, l.height)).collect();
+    assert_eq!(sizes, [(64, 32), (32, 16), (16, 8), (8, 4)]);
+    let blocks: u32 = (&c_data).into_iter().map(|l| l.blocks_x * l.blocks_y).sum();
+    assert_eq!(blocks, 128 + 32 + 8 + 2);
+    assert_eq!(CrunchedData::new(b"Hx").levels().len(), 0);
+}
+
+#[test]
+fn faces_address_cubemap_faces() {
+    let mut data = synthetic_crn(16, 16, 2, 0, 0);
+    data[17] = 6;
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(c_data.faces().len(), 6);
+    assert_eq!(c_data.level_info(1).faces, 6);
+    let info = c_data.face_level_info(5, 1).unwrap();
+    assert_eq!((info.width, info.faces), (8, 1));
+    assert_eq!(
+        c_data.face_level_info(6, 0).unwrap_err(),
+        DecrunchError::FaceOutOfRange { face: 6, faces: 6 }
+    );
+    assert_eq!(
+        CrunchedData::new(&synthetic_crn(8, 8, 1, 0, 0)).faces(),
+        0..1
+    );
+}
+
+#[test]
+fn decode_all_levels_reports_first_failure() {
+    let data = synthetic_crn(16, 16, 2, 0, 0);
+    assert_eq!(
+        CrunchedData::new(&data).decode_all_levels(),
+        Err(DecrunchError::CorruptTables)
+    );
+    assert_eq!(
+        CrunchedData::new(b"Hx").decode_all_levels(),
+        Err(DecrunchError::InvalidHeader)
+    );
+}
+
+#[test]
+fn decoded_level_describes_itself() {
+    let info = CrunchedData::new(&synthetic_crn(24, 8, 1, 2, 0)).level_info(0);
+    let level = DecodedLevel::new(&info, vec![0; 96]);
+    assert_eq!(
+        (level.blocks_x, level.blocks_y, level.row_pitch),
+        (6, 2, 96)
+    );
+    assert_eq!(level.format, CrnFormat::Dxt5);
+    assert_eq!(level.as_ref().len(), 96);
+    assert_eq!(
+        CrunchedData::new(b"Hx").decode(0),
+        Err(DecrunchError::InvalidHeader)
+    );
+}
+
+#[test]
+fn accessors_read_texture_info() {
+    let data = synthetic_crn(40, 24, 3, 2, 0);
+    let c_data = CrunchedData::new(&data);
+    assert_eq!((c_data.width(), c_data.height()), (40, 24));
+    assert_eq!((c_data.level_count(), c_data.face_count()), (3, 1));
+    assert_eq!(c_data.format(), CrnFormat::Dxt5);
+    let invalid = CrunchedData::new(b"Hx");
+    assert_eq!((invalid.width(), invalid.level_count()), (0, 0));
+    assert_eq!(invalid.format(), CrnFormat::Invalid);
+}
+
+#[test]
+fn indices_are_checked_against_texture() {
+    let data = synthetic_crn(32, 32, 3, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    let level = c_data.level(2).unwrap();
+    assert_eq!(level.get(), 2);
+    assert_eq!(c_data.level_info_at(level).width, 8);
+    assert_eq!(
+        c_data.level(3),
+        Err(DecrunchError::LevelOutOfRange {
+            level: 3,
+            levels: 3
+        })
+    );
+    assert_eq!(u32::from(c_data.face(0).unwrap()), 0);
+    assert_eq!(
+        c_data.face(1),
+        Err(DecrunchError::FaceOutOfRange { face: 1, faces: 1 })
+    );
+
+    // The checked overloads behave like the plain ones.
+    let face = c_data.face(0).unwrap();
+    assert_eq!(c_data.decode_level_at(level), c_data.decode_level(2));
+    assert_eq!(c_data.decode_at(level), c_data.decode(2));
+    assert_eq!(c_data.decode_face_at(level, face), c_data.decode_face(2, 0));
+    let mut dst = vec![0; 64];
+    assert_eq!(
+        c_data.decode_level_into_at(level, &mut dst),
+        c_data.decode_level_into(2, &mut dst)
+    );
+}
+
+#[test]
+fn truncated_data_is_reported() {
+    let data = synthetic_crn(16, 16, 2, 0, 0);
+    let err = CrunchedData::new(&data[..100])
+        .try_texture_info()
+        .unwrap_err();
+    assert_eq!(
+        err,
+        DecrunchError::Truncated {
+            size: 100,
+            expected: data.len()
+        }
+    );
+    assert_eq!(err.to_string(), "data truncated to 100 of 110 bytes");
+    assert_eq!(CrunchedData::try_new(&data[..100]).err(), Some(err));
+}
+
+#[test]
+fn format_names_round_trip() {
+    assert_eq!(CrnFormat::Dxt5xGBR.to_string(), "DXT5_xGBR");
+    assert_eq!(CrnFormat::Invalid.to_string(), "invalid");
+    assert_eq!("dxt5_agbr".parse(), Ok(CrnFormat::Dxt5Agbr));
+    assert_eq!(CrnFormat::from_name("ETC2AS"), Some(CrnFormat::Etc2AS));
+    assert_eq!("BC7".parse::<CrnFormat>(), Err(ParseFormatError));
+}
+
+#[test]
+fn format_raw_conversions() {
+    assert_eq!(CrnFormat::Dxt5A.as_raw(), 9);
+    assert_eq!(CrnFormat::Invalid.as_raw(), -1);
+    assert_eq!(CrnFormat::try_from(14), Ok(CrnFormat::Etc2AS));
+    assert_eq!(CrnFormat::try_from(15), Err(ParseFormatError));
+    assert_eq!(CrnFormat::try_from(-1), Err(ParseFormatError));
+    for raw in 0..15 {
+        assert_eq!(CrnFormat::try_from(raw).unwrap().as_raw(), raw);
+    }
+}
+
+#[test]
+fn format_metadata() {
+    let dxt1 = CrnFormat::Dxt1;
+    assert_eq!((dxt1.bytes_per_block(), dxt1.channel_count()), (8, 3));
+    assert!(!dxt1.has_alpha() && !dxt1.is_swizzled());
+    assert_eq!(CrnFormat::DxNXy.bytes_per_block(), 16);
+    assert_eq!(CrnFormat::DxNXy.channel_count(), 2);
+    assert!(CrnFormat::Etc2AS.has_alpha());
+    assert!(CrnFormat::Dxt5xGBR.is_swizzled());
+    assert_eq!(
+        (
+            CrnFormat::Dxt5A.block_width(),
+            CrnFormat::Dxt5A.block_height()
+        ),
+        (4, 4)
+    );
+    assert_eq!(CrnFormat::Invalid.bytes_per_block(), 0);
+    assert_eq!(CrnFormat::Total.block_width(), 0);
+}
+
+#[test]
+fn debug_summarizes_texture() {
+    let data = synthetic_crn(64, 32, 2, 0, 0);
+    assert_eq!(
+        format!("{:?}", CrunchedData::new(&data)),
+        "CrunchedData { width: 64, height: 32, levels: 2, faces: 1, format: Dxt1, size: 110 }"
+    );
+}
+
+#[test]
+fn info_structs_match_crunch_abi() {
+    let data = synthetic_crn(16, 16, 1, 3, 0);
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(c_data.level_info(0).struct_size, 32);
+    assert_eq!(c_data.texture_info().struct_size, 36);
+    assert_eq!(c_data.level_info(0).format, CrnFormat::Dxt5cCxY);
+}
+
+#[test]
+fn unknown_format_yields_no_info() {
+    let data = synthetic_crn(16, 16, 1, 20, 0);
+    assert!(crunch::get_level_info(&data, 0).is_none());
+    assert!(crunch::get_texture_info(&data).is_none());
+    assert_eq!(
+        CrunchedData::new(&data).level_info(0).format,
+        CrnFormat::Invalid
+    );
+}
+
+#[test]
+fn decode_with_pitch_checks_layout() {
+    let data = synthetic_crn(16, 16, 1, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(
+        c_data.decode_level_with_pitch(0, 24),
+        Err(DecrunchError::PitchTooSmall { pitch: 24, min: 32 })
+    );
+    assert_eq!(
+        c_data.decode_level_with_pitch(0, 34),
+        Err(DecrunchError::InvalidPitch { pitch: 34 })
+    );
+    assert_eq!(
+        c_data.decode_level_into_with_pitch(0, &mut [0; 200], 34),
+        Err(DecrunchError::InvalidPitch { pitch: 34 })
+    );
+    assert_eq!(
+        c_data.decode_level_into_with_pitch(0, &mut [0; 200], 64),
+        Err(DecrunchError::BufferTooSmall {
+            needed: 256,
+            actual: 200
+        })
+    );
+    assert_eq!(
+        c_data.decode_level_with_pitch(0, 64),
+        Err(DecrunchError::CorruptTables)
+    );
+}
+
+#[test]
+fn aligned_row_pitch_rounds_up() {
+    let data = synthetic_crn(40, 8, 2, 2, 0);
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(c_data.aligned_row_pitch(0, 256), Ok(256));
+    assert_eq!(c_data.aligned_row_pitch(1, 64), Ok(128));
+    assert_eq!(c_data.aligned_row_pitch(0, 0), Ok(160));
+    assert_eq!(c_data.aligned_row_pitch(0, 3), Ok(168));
+    assert_eq!(c_data.aligned_row_pitch(1, 6), Ok(84));
+    assert_eq!(
+        c_data.aligned_row_pitch(0, u32::MAX),
+        Err(DecrunchError::OutOfLimits {
+            field: "row alignment",
+            value: u32::MAX
+        })
+    );
+    assert_eq!(
+        c_data.decode_aligned(0, 256),
+        Err(DecrunchError::CorruptTables)
+    );
+}
+
+#[test]
+fn flip_mirrors_blocks() {
+    // DXT5 block with alpha and color indices differing per row.
+    let mut block = [
+        0xFF, 0x00, 0x00, 0x10, 0x32, 0x54, 0x76, 0x00, 0x00, 0xF8, 0x1F, 0x00, 0x00, 0x55, 0xAA,
+        0xFF,
+    ];
+    let before = block::decode_rgba(CrnFormat::Dxt5, &block).unwrap();
+    assert!(block::flip_block(CrnFormat::Dxt5, &mut block));
+    let after = block::decode_rgba(CrnFormat::Dxt5, &block).unwrap();
+    for y in 0..4 {
+        assert_eq!(
+            after[y * 4..y * 4 + 4],
+            before[(3 - y) * 4..(3 - y) * 4 + 4]
+        );
+    }
+    assert!(!block::flip_block(CrnFormat::Etc1, &mut [0; 8]));
+    assert_eq!(
+        DecrunchError::FlipUnsupported(CrnFormat::Etc1).to_string(),
+        "ETC1 blocks cannot be flipped"
+    );
+
+    let info = LevelInfo {
+        blocks_x: 1,
+        blocks_y: 3,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let mut level = [
+        1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 2, 3, 4, 3, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    assert!(block::flip_level(&info, &mut level, 8));
+    assert_eq!(level[..8], [3, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(level[8..16], [2, 0, 0, 0, 4, 3, 2, 1]);
+    assert_eq!(level[16], 1);
+}
+
+#[test]
+fn decode_levels_checks_range() {
+    let data = synthetic_crn(16, 16, 3, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(
+        c_data.decode_levels(1..4),
+        Err(DecrunchError::LevelOutOfRange {
+            level: 3,
+            levels: 3
+        })
+    );
+    assert_eq!(c_data.decode_levels(2..2), Ok(Vec::new()));
+    assert_eq!(
+        c_data.decode_levels(1..3),
+        Err(DecrunchError::CorruptTables)
+    );
+}
+
+#[test]
+fn decoded_size_helpers() {
+    let data = synthetic_crn(32, 16, 3, 2, 0);
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(c_data.expected_level_size(0), Some(8 * 4 * 16));
+    assert_eq!(c_data.expected_level_size(3), None);
+    assert_eq!(c_data.total_decoded_size(), Some(512 + 128 + 32));
+
+    let mut cube = synthetic_crn(16, 16, 1, 0, 0);
+    cube[17] = 6;
+    assert_eq!(
+        CrunchedData::new(&cube).expected_level_size(0),
+        Some(6 * 128)
+    );
+    assert_eq!(CrunchedData::new(b"Hx").total_decoded_size(), None);
+}
+
+#[test]
+fn decoder_options_apply_to_decode() {
+    let options = DecoderOptions::new()
+        .with_row_alignment(0)
+        .with_flip_vertical(true);
+    assert_eq!(options.row_alignment(), 1);
+    assert!(options.flip_vertical());
+
+    let data = synthetic_crn(16, 16, 1, 0, 0);
+    let c_data = CrunchedData::with_options(&data, options.clone());
+    assert_eq!(c_data.options(), &options);
+    assert_eq!(
+        CrunchedData::new(&data).options(),
+        &DecoderOptions::default()
+    );
+    assert_eq!(c_data.decode(0), Err(DecrunchError::CorruptTables));
+}
+
+#[test]
+fn output_size_is_capped() {
+    let data = synthetic_crn(64, 64, 1, 0, 0);
+    let options = DecoderOptions::new().with_max_output_size(1000);
+    let c_data = CrunchedData::with_options(&data, options);
+    let err = DecrunchError::OutputTooLarge {
+        size: 2048,
+        limit: 1000,
+    };
+    assert_eq!(c_data.try_decode_level(0), Err(err.clone()));
+    assert_eq!(c_data.decode(0), Err(err));
+    assert_eq!(c_data.decode_level(0), None);
+    assert_eq!(
+        DecoderOptions::default().max_output_size(),
+        DEFAULT_MAX_OUTPUT_SIZE
+    );
+}
+
+#[test]
+fn header_limits_are_enforced() {
+    let kind = |data: &[u8]| CrunchedData::try_new(data).err();
+    let limit = |field, value| Some(DecrunchError::OutOfLimits { field, value });
+    assert_eq!(
+        kind(&synthetic_crn(8192, 16, 1, 0, 0)),
+        limit("width", 8192)
+    );
+    assert_eq!(kind(&synthetic_crn(16, 0, 1, 0, 0)), limit("height", 0));
+    assert_eq!(
+        kind(&synthetic_crn(16, 16, 6, 0, 0)),
+        limit("level count", 6)
+    );
+    let mut data = synthetic_crn(16, 16, 1, 0, 0);
+    data[17] = 2;
+    assert_eq!(kind(&data), limit("face count", 2));
+    let mut data = synthetic_crn(16, 16, 2, 0, 0);
+    data[70..74].copy_from_slice(&10u32.to_be_bytes());
+    assert_eq!(kind(&data), limit("level offset", 10));
+    let mut data = synthetic_crn(16, 16, 2, 0, 0);
+    data[74..78].copy_from_slice(&500u32.to_be_bytes());
+    assert_eq!(kind(&data), limit("level offset", 78));
+    assert_eq!(
+        DecrunchError::OutOfLimits {
+            field: "width",
+            value: 8192
+        }
+        .to_string(),
+        "width of 8192 is outside crunch's limits"
+    );
+}
+
+#[test]
+fn lenient_decode_reports_each_level() {
+    let data = synthetic_crn(16, 16, 3, 0, 0);
+    let results = CrunchedData::new(&data).decode_levels_lenient();
+    assert_eq!(results.len(), 3);
+    assert!(results
+        .iter()
+        .all(|r| r == &Err(DecrunchError::CorruptTables)));
+    assert!(CrunchedData::new(b"Hx").decode_levels_lenient().is_empty());
+}
+
+#[test]
+fn raw_ctx_round_trip() {
+    let data = synthetic_crn(16, 16, 1, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    assert!(c_data.as_raw_ctx().is_null());
+    let wrapped = unsafe { CrunchedData::from_raw_ctx(&data, ptr::null()) };
+    assert_eq!(wrapped.width(), 16);
+    assert_eq!(
+        wrapped.try_decode_level(0),
+        Err(DecrunchError::CorruptTables)
+    );
+}
+
+#[test]
+fn sys_functions_are_callable() {
+    let data = synthetic_crn(32, 16, 2, 0, 0);
+    let mut info = sys::RawLevelInfo {
+        struct_size: 32,
+        ..Default::default()
+    };
+    let ok = unsafe { sys::crnd_get_level_info(data.as_ptr(), data.len() as u32, 1, &mut info) };
+    assert!(ok);
+    assert_eq!((info.width, info.height, info.format), (16, 8, 0));
+}
+
+#[test]
+fn clone_has_own_context() {
+    let data = synthetic_crn(16, 16, 1, 0, 0);
+    let options = DecoderOptions::new().with_row_alignment(64);
+    let c_data = CrunchedData::with_options(&data, options.clone());
+    let clone = c_data.clone();
+    assert_eq!(clone.options(), &options);
+    assert_eq!(clone.buffer.as_ptr(), data.as_ptr());
+    assert_eq!(format!("{:?}", clone), format!("{:?}", c_data));
+}
+
+#[test]
+fn close_reports_teardown() {
+    let data = synthetic_crn(16, 16, 1, 0, 0);
+    assert_eq!(CrunchedData::new(&data).close(), Ok(()));
+    assert!(!crunch::unpack_end(ptr::null()));
+    assert_eq!(
+        DecrunchError::CloseFailed.to_string(),
+        "failed to free the decoder context"
+    );
+}
+
+#[test]
+fn cancelled_decode_stops() {
+    let token = CancelToken::new();
+    let data = synthetic_crn(16, 16, 3, 0, 0);
+    let options = DecoderOptions::new().with_cancel_token(token.clone());
+    let c_data = CrunchedData::with_options(&data, options);
+    assert_eq!(c_data.options().cancel_token(), Some(&token));
+    assert_eq!(c_data.decode(0), Err(DecrunchError::CorruptTables));
+    token.cancel();
+    assert!(token.is_cancelled());
+    assert_eq!(c_data.decode_levels(0..3), Err(DecrunchError::Cancelled));
+    assert!(c_data
+        .decode_levels_lenient()
+        .iter()
+        .all(|r| r == &Err(DecrunchError::Cancelled)));
+    assert_ne!(token, CancelToken::new());
+}
+
+#[test]
+fn progress_is_reported_per_level() {
+    let data = synthetic_crn(16, 16, 2, 0, 0);
+    let mut reports: Vec<Progress> = Vec::new();
+    let result = CrunchedData::new(&data).decode_all_levels_with_progress(|p| reports.push(p));
+    assert_eq!(result, Err(DecrunchError::CorruptTables));
+    assert!(reports.is_empty());
+}
+
+#[test]
+fn constructors_accept_byte_like_buffers() {
+    let data = synthetic_crn(16, 16, 1, 0, 0);
+    let boxed: Box<[u8]> = data.clone().into_boxed_slice();
+    let cow: std::borrow::Cow<[u8]> = std::borrow::Cow::Borrowed(&data);
+    assert_eq!(CrunchedData::new(&data).width(), 16);
+    assert_eq!(CrunchedData::new(&boxed).width(), 16);
+    assert_eq!(CrunchedData::new(&cow).width(), 16);
+    let result = CrunchedData::try_from(&data[..2]);
+    assert_eq!(result.unwrap_err(), DecrunchError::InvalidHeader);
+}
+
+#[test]
+fn is_crn_sniffs_the_header() {
+    let data = synthetic_crn(16, 16, 1, 0, 0);
+    assert!(is_crn(&data));
+    assert_eq!(data[..2], CRN_MAGIC);
+    assert!(!is_crn(&data[..CRN_MIN_HEADER_SIZE - 1]));
+    assert!(!is_crn(b"DDS \x7c\0\0\0"));
+    let mut bad_size = data.clone();
+    bad_size[3] = 10;
+    bad_size[2] = 0;
+    assert!(!is_crn(&bad_size));
+}
+
+#[test]
+fn header_exposes_raw_fields() {
+    let data = synthetic_crn(16, 8, 2, 2, 0);
+    let header = CrunchedData::new(&data).header().unwrap();
+    assert_eq!((header.width, header.height), (16, 8));
+    assert_eq!(header.levels, 2);
+    assert_eq!(header.format, 2);
+    assert_eq!(header.level_ofs.len(), 2);
+    assert_eq!(header.data_size as usize, data.len());
+    assert!(!header.is_segmented());
+    assert_eq!(CrnHeader::parse(&data), Some(header));
+    assert_eq!(
+        CrunchedData::new(&data[..10]).header(),
+        Err(DecrunchError::InvalidHeader)
+    );
+}
+
+#[test]
+fn rgba_of_dxt1_punch_through_block() {
+    // Three-color mode: black and white endpoints, index 3 is transparent.
+    let decoded = [0x00, 0x00, 0xFF, 0xFF, 0b1110_0100, 0, 0, 0];
+    let info = LevelInfo {
+        width: 4,
+        height: 4,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let rgba = pixels::rgba(&info, &decoded).unwrap();
+    assert_eq!(rgba.len(), 64);
+    assert_eq!(
+        rgba[..16],
+        [0, 0, 0, 255, 255, 255, 255, 255, 127, 127, 127, 255, 0, 0, 0, 0]
+    );
+
+    let data = synthetic_crn(16, 16, 1, 0, 0);
+    assert_eq!(CrunchedData::new(&data).decode_level_to_rgba(0), None);
+}
+
+#[test]
+fn etc1_blocks_decode_to_rgba() {
+    // Individual mode, side by side: red 8 on the left, 0 on the right,
+    // table 0 and all indices 0, so every channel gets +2.
+    let texels = block::decode_rgba(CrnFormat::Etc1, &[0x80, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+    assert_eq!(texels[0], [138, 2, 2, 255]);
+    assert_eq!(texels[2], [2, 2, 2, 255]);
+    assert_eq!(texels[15], [2, 2, 2, 255]);
+
+    // Differential mode, flipped: red 31 on top and 30 below; texel (0, 0)
+    // uses index 2, which subtracts.
+    let block = [0xFF, 0, 0, 0x03, 0, 0x01, 0, 0];
+    let texels = block::decode_rgba(CrnFormat::Etc1, &block).unwrap();
+    assert_eq!(texels[0], [253, 0, 0, 255]);
+    assert_eq!(texels[3], [255, 2, 2, 255]);
+    assert_eq!(texels[8], [249, 2, 2, 255]);
+}
+
+#[test]
+fn transcode_applies_channel_order() {
+    // One DXT1 block, solid red.
+    let decoded = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+    let info = LevelInfo {
+        width: 1,
+        height: 1,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let transcode = |order| {
+        let options = DecoderOptions::new().with_channel_order(order);
+        pixels::transcode(&info, &decoded, &options).unwrap()
+    };
+    assert_eq!(DecoderOptions::new().channel_order(), ChannelOrder::Rgba);
+    assert_eq!(transcode(ChannelOrder::Rgba), [255, 0, 0, 255]);
+    assert_eq!(transcode(ChannelOrder::Bgra), [0, 0, 255, 255]);
+    assert_eq!(transcode(ChannelOrder::Argb), [255, 255, 0, 0]);
+}
+
+#[test]
+fn sixteen_bit_pixel_formats() {
+    let pixel = |format: PixelFormat, texel| {
+        let mut out = [0; 4];
+        format.write(texel, &mut out);
+        out
+    };
+    assert_eq!(PixelFormat::Rgb565.bytes_per_pixel(), 2);
+    assert_eq!(
+        pixel(PixelFormat::Rgb565, [255, 0, 0, 0]),
+        [0x00, 0xF8, 0, 0]
+    );
+    assert_eq!(
+        pixel(PixelFormat::Rgb565, [0, 255, 0, 0]),
+        [0xE0, 0x07, 0, 0]
+    );
+    assert_eq!(
+        pixel(PixelFormat::Rgba4444, [255, 0, 136, 17]),
+        [0x81, 0xF0, 0, 0]
+    );
+    assert_eq!(pixel(PixelFormat::Rgba8, [1, 2, 3, 4]), [1, 2, 3, 4]);
+}
+
+#[test]
+fn dxt5a_transcodes_to_r8() {
+    // One DXT5A block with endpoints 200 and 100; every index is 1.
+    let decoded = [200, 100, 0x49, 0x92, 0x24, 0x49, 0x92, 0x24];
+    let info = LevelInfo {
+        width: 2,
+        height: 1,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt5A,
+        ..LevelInfo::default()
+    };
+    let options = DecoderOptions::new().with_pixel_format(PixelFormat::R8);
+    assert_eq!(
+        pixels::transcode(&info, &decoded, &options).unwrap(),
+        [100, 100]
+    );
+    assert_eq!(
+        pixels::rgba(&info, &decoded).unwrap()[..4],
+        [100, 100, 100, 255]
+    );
+}
+
+#[test]
+fn dxn_transcodes_to_rg8() {
+    // Two alpha blocks with all indices 0: the first holds 10, the second 20.
+    let mut decoded = [0; 16];
+    decoded[0] = 10;
+    decoded[8] = 20;
+    let mut info = LevelInfo {
+        width: 1,
+        height: 1,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 16,
+        format: CrnFormat::DxNXy,
+        ..LevelInfo::default()
+    };
+    let options = DecoderOptions::new().with_pixel_format(PixelFormat::Rg8);
+    assert_eq!(
+        pixels::transcode(&info, &decoded, &options).unwrap(),
+        [10, 20]
+    );
+    info.format = CrnFormat::DxNYx;
+    assert_eq!(
+        pixels::transcode(&info, &decoded, &options).unwrap(),
+        [20, 10]
+    );
+}
+
+#[test]
+fn dxn_normal_z_is_reconstructed() {
+    assert_eq!(
+        pixels::reconstruct_z([128, 128, 0, 255]),
+        [128, 128, 255, 255]
+    );
+    assert_eq!(pixels::reconstruct_z([255, 128, 0, 255])[2], 128);
+
+    // A DxN_YX block whose X and Y are both 128, so Z points straight out.
+    let mut decoded = [0; 16];
+    decoded[0] = 128;
+    decoded[8] = 128;
+    let info = LevelInfo {
+        width: 1,
+        height: 1,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 16,
+        format: CrnFormat::DxNYx,
+        ..LevelInfo::default()
+    };
+    let options = DecoderOptions::new().with_reconstruct_z(true);
+    assert_eq!(
+        pixels::transcode(&info, &decoded, &options).unwrap(),
+        [128, 128, 255, 255]
+    );
+    let plain = DecoderOptions::new();
+    assert_eq!(
+        pixels::transcode(&info, &decoded, &plain).unwrap(),
+        [128, 128, 0, 255]
+    );
+}
+
+#[test]
+fn swizzled_dxt5_is_restored() {
+    let texel = [10, 20, 30, 40];
+    assert_eq!(
+        pixels::unswizzle(CrnFormat::Dxt5xGxR, texel),
+        [40, 20, 0, 255]
+    );
+    assert_eq!(
+        pixels::unswizzle(CrnFormat::Dxt5xGBR, texel),
+        [40, 20, 30, 255]
+    );
+    assert_eq!(
+        pixels::unswizzle(CrnFormat::Dxt5Agbr, texel),
+        [40, 20, 30, 10]
+    );
+    assert_eq!(pixels::unswizzle(CrnFormat::Dxt5, texel), texel);
+
+    // A DXT5 xGBR block: alpha 255 (red), color solid green.
+    let mut decoded = [0; 16];
+    decoded[..2].copy_from_slice(&[255, 255]);
+    decoded[8..12].copy_from_slice(&[0xE0, 0x07, 0xE0, 0x07]);
+    let info = LevelInfo {
+        width: 1,
+        height: 1,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 16,
+        format: CrnFormat::Dxt5xGBR,
+        ..LevelInfo::default()
+    };
+    let options = DecoderOptions::new().with_unswizzle(true);
+    assert_eq!(
+        pixels::transcode(&info, &decoded, &options).unwrap(),
+        [255, 255, 0, 255]
+    );
+    let plain = DecoderOptions::new();
+    assert_eq!(
+        pixels::transcode(&info, &decoded, &plain).unwrap(),
+        [0, 255, 0, 255]
+    );
+}
+
+#[test]
+fn premultiplied_alpha() {
+    assert_eq!(pixels::premultiply([255, 128, 0, 128]), [128, 64, 0, 128]);
+    assert_eq!(
+        pixels::premultiply([200, 100, 50, 255]),
+        [200, 100, 50, 255]
+    );
+
+    // The punch-through DXT1 block used above: index 3 becomes transparent
+    // black, the rest stay opaque.
+    let decoded = [0x00, 0x00, 0xFF, 0xFF, 0b1110_0100, 0, 0, 0];
+    let info = LevelInfo {
+        width: 4,
+        height: 1,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let options = DecoderOptions::new()
+        .with_premultiply_alpha(true)
+        .with_channel_order(ChannelOrder::Argb);
+    let pixels = pixels::transcode(&info, &decoded, &options).unwrap();
+    assert_eq!(pixels[4..8], [255, 255, 255, 255]);
+    assert_eq!(pixels[12..], [0, 0, 0, 0]);
+}
+
+#[test]
+fn color_space_conversion() {
+    for value in [0, 1, 64, 128, 200, 255] {
+        assert_eq!(pixels::linear_to_srgb(pixels::srgb_to_linear(value)), value);
+    }
+    assert_eq!(GraphicsFormat::RgbaDxt5Srgb.color_space(), ColorSpace::Srgb);
+    assert_eq!(
+        GraphicsFormat::RgbaDxt5UNorm.color_space(),
+        ColorSpace::Linear
+    );
+
+    // One DXT1 block, solid 5:6:5 gray (132, 130, 132).
+    let decoded = [0x10, 0x84, 0x10, 0x84, 0, 0, 0, 0];
+    let info = LevelInfo {
+        width: 1,
+        height: 1,
+        faces: 1,
+        blocks_x: 1,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let transcode = |options| pixels::transcode(&info, &decoded, &options).unwrap();
+    let to_linear = DecoderOptions::new().with_output_color_space(ColorSpace::Linear);
+    assert_eq!(transcode(to_linear), [59, 57, 59, 255]);
+    let to_srgb = DecoderOptions::new()
+        .with_color_space(ColorSpace::Linear)
+        .with_output_color_space(ColorSpace::Srgb);
+    assert_eq!(transcode(to_srgb), [190, 189, 190, 255]);
+    let unchanged = DecoderOptions::new().with_output_color_space(ColorSpace::Srgb);
+    assert_eq!(transcode(unchanged), [132, 130, 132, 255]);
+
+    let linear = pixels::linear(&info, &decoded, ColorSpace::Linear, None).unwrap();
+    assert_eq!(linear[0], 132.0 / 255.0);
+}
+
+#[test]
+fn dithering_spreads_values_between_steps() {
+    // 4 lies just under halfway between the first two 5-bit steps, 0 and 8,
+    // so a little under half the pixels round up.
+    let reds = |dither: bool| {
+        let mut reds = Vec::new();
+        for y in 0..4 {
+            for x in 0..4 {
+                let mut texel = [4, 0, 0, 255];
+                if dither {
+                    texel = PixelFormat::Rgb565.dither(x, y, texel);
+                }
+                let mut out = [0; 2];
+                PixelFormat::Rgb565.write(texel, &mut out);
+                reds.push(u16::from_le_bytes(out) >> 11);
+            }
+        }
+        reds
+    };
+    assert!(reds(false).iter().all(|&r| r == 0));
+    let dithered = reds(true);
+    assert_eq!(dithered.iter().filter(|&&r| r == 1).count(), 7);
+    assert!(dithered.iter().all(|&r| r <= 1));
+    assert_eq!(PixelFormat::Rgba8.dither(1, 2, [4, 5, 6, 7]), [4, 5, 6, 7]);
+}
+
+#[test]
+fn cubemap_levels_hold_every_face() {
+    let mut data = synthetic_crn(16, 16, 1, 0, 0);
+    data[17] = 6;
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(c_data.expected_level_size(0), Some(6 * 128));
+    let mut dst = vec![0; 128];
+    assert_eq!(
+        c_data.decode_level_into(0, &mut dst),
+        Err(DecrunchError::BufferTooSmall {
+            needed: 6 * 128,
+            actual: 128
+        })
+    );
+
+    // Two faces of one row of two DXT1 blocks: each face is flipped on its
+    // own.
+    let info = LevelInfo {
+        width: 8,
+        height: 4,
+        faces: 2,
+        blocks_x: 2,
+        blocks_y: 1,
+        bytes_per_block: 8,
+        format: CrnFormat::Dxt1,
+        ..LevelInfo::default()
+    };
+    let mut faces: Vec<u8> = (0..32).collect();
+    assert!(block::flip_level(&info, &mut faces, 16));
+    assert_eq!(faces[4..8], [7, 6, 5, 4]);
+    assert_eq!(faces[20..24], [23, 22, 21, 20]);
+    assert_eq!(DecodedLevel::new(&info, faces).faces, 2);
+}
+
+#[test]
+fn decode_face_checks_the_face() {
+    let mut data = synthetic_crn(16, 16, 1, 0, 0);
+    let c_data = CrunchedData::new(&data);
+    assert_eq!(
+        c_data.decode_face(0, 1),
+        Err(DecrunchError::FaceOutOfRange { face: 1, faces: 1 })
+    );
+    assert_eq!(c_data.decode_face(0, 0), Err(DecrunchError::CorruptTables));
+    drop(c_data);
+    data[17] = 6;
+    let cube = CrunchedData::new(&data);
+    assert_eq!(cube.decode_face(0, 5), Err(DecrunchError::CorruptTables));
+}
+
+#[test]
+fn packed_texture_layouts() {
+    let level = |blocks: u32, fill: u8| DecodedLevel {
+        width: blocks * 4,
+        height: 4,
+        blocks_x: blocks,
+        blocks_y: 1,
+        row_pitch: blocks * 8,
+        faces: 2,
+        format: CrnFormat::Dxt1,
+        // Face 0 holds `fill`, face 1 `fill + 1`.
+        data: [
+            vec![fill; (blocks * 8) as usize],
+            vec![fill + 1; (blocks * 8) as usize],
+        ]
+        .concat(),
+    };
+    let texture = DecodedTexture {
+        width: 8,
+        height: 4,
+        faces: 2,
+        format: CrnFormat::Dxt1,
+        levels: vec![level(2, 10), level(1, 20)],
+    };
+
+    let by_level = texture.pack(TextureLayout::LevelMajor);
+    assert_eq!(by_level.data.len(), 48);
+    assert_eq!(by_level.range(1, 0), Some(16..32));
+    assert_eq!(by_level.range(0, 1), Some(32..40));
+    assert_eq!(by_level.data[16], 11);
+
+    let by_face = texture.pack(TextureLayout::FaceMajor);
+    assert_eq!(by_face.range(0, 1), Some(16..24));
+    assert_eq!(by_face.range(1, 0), Some(24..40));
+    assert_eq!(by_face.data[40..], [21; 8]);
+    assert_eq!(by_face.range(2, 0), None);
+}
+
+/// Reads one of the fixtures written by `testdata/fixtures.cpp`.
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(format!("testdata/{}", name)).unwrap()
+}
+
+/// Colors of the top-left, top-right, bottom-left and bottom-right quarters
+/// of `quadrants_dxt1.crn`.
+const QUADRANT_COLORS: [[u8; 4]; 4] = [
+    [255, 0, 0, 255],
+    [0, 255, 0, 255],
+    [0, 0, 255, 255],
+    [255, 255, 255, 255],
+];
+
+/// Colors of the faces of `cube_dxt5.crn`.
+const FACE_COLORS: [[u8; 4]; 6] = [
+    [255, 0, 0, 255],
+    [0, 255, 0, 204],
+    [0, 0, 255, 153],
+    [255, 255, 0, 102],
+    [255, 0, 255, 51],
+    [0, 255, 255, 0],
+];
+
+#[test]
+fn fixture_decodes_quadrants() {
+    let data = fixture("quadrants_dxt1.crn");
+    let c_data = CrunchedData::try_new(&data).unwrap();
+    let info = c_data.try_texture_info().unwrap();
+    assert_eq!(
+        (info.width(), info.height(), info.levels(), info.format()),
+        (32, 16, 6, CrnFormat::Dxt1)
+    );
+
+    // Down to 8x4, every block holds a single quarter.
+    for level in 0..3 {
+        let level_info = c_data.level_info(level);
+        let (width, height) = (level_info.width(), level_info.height());
+        let rgba = c_data.decode_level_to_rgba(level).unwrap();
+        assert_eq!(rgba.len(), (width * height * 4) as usize);
+        let pixel = |x: u32, y: u32| &rgba[((y * width + x) * 4) as usize..][..4];
+        for (i, color) in QUADRANT_COLORS.iter().enumerate() {
+            let x = i as u32 % 2 * width / 2;
+            let y = i as u32 / 2 * height / 2;
+            assert_eq!(pixel(x, y), color);
+            assert_eq!(pixel(x + width / 2 - 1, y + height / 2 - 1), color);
+        }
+    }
+
+    let level = c_data.try_decode_level(0).unwrap();
+    assert_eq!(level.len(), 8 * 4 * 8);
+    assert_eq!(
+        block::decode_rgba(CrnFormat::Dxt1, &level[..8]),
+        Some([QUADRANT_COLORS[0]; 16])
+    );
+    let mut dst = vec![0xAA; level.len() + 8];
+    c_data.decode_level_into(0, &mut dst).unwrap();
+    assert_eq!(dst[..level.len()], level[..]);
+    assert_eq!(dst[level.len()..], [0xAA; 8]);
+
+    // The blocks fit in 1000 bytes, the RGBA pixels do not.
+    let options = DecoderOptions::new().with_max_output_size(1000);
+    let capped = CrunchedData::with_options(&data, options);
+    assert_eq!(capped.try_decode_level(0), Ok(level));
+    assert_eq!(capped.decode_level_to_rgba(0), None);
+}
+
+#[test]
+fn fixture_decodes_through_every_entry_point() {
+    let data = fixture("quadrants_dxt1.crn");
+    let mut c_data = CrunchedData::new(&data);
+    let level0 = c_data.try_decode_level(0).unwrap();
+    let level1 = c_data.try_decode_level(1).unwrap();
+
+    // A cached level is decoded once and then borrowed.
+    let cached = c_data.cache_level(0).unwrap().as_ptr();
+    assert_eq!(c_data.cache_level(0).unwrap().as_ptr(), cached);
+    assert_eq!(c_data.cached_level(0), Some(&level0[..]));
+
+    // The bottom-right quarter is white.
+    let tile = Tile {
+        x: 16,
+        y: 8,
+        width: 16,
+        height: 8,
+        border: 0,
+    };
+    let blocks = c_data.decode_tile(0, tile).unwrap();
+    assert_eq!(blocks.len(), 4 * 2 * 8);
+    for block in blocks.chunks(8) {
+        assert_eq!(
+            block::decode_rgba(CrnFormat::Dxt1, block),
+            Some([QUADRANT_COLORS[3]; 16])
+        );
+    }
+
+    let packed = c_data.decode_all_packed(TextureLayout::LevelMajor).unwrap();
+    assert_eq!(packed.levels, 6);
+    assert_eq!(packed.data[packed.range(0, 0).unwrap()], level0[..]);
+    assert_eq!(packed.data[packed.range(0, 1).unwrap()], level1[..]);
+
+    let mut reader = CrunchedReader::new(Cursor::new(&data)).unwrap();
+    assert_eq!(reader.decode_level(0).unwrap(), level0);
+    assert_eq!(reader.decode_level(1).unwrap(), level1);
+
+    // Without its last byte, the file only loses the 1x1 level.
+    let recovered = CrunchedData::decode_truncated(&data[..data.len() - 1]).unwrap();
+    assert_eq!(recovered.levels.len(), 5);
+    assert_eq!(recovered.levels[0], level0);
+    assert_eq!(recovered.loss.lost_levels, 5..6);
+
+    let dir = env::temp_dir().join(format!("decrunch-fixtures-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("quadrants.crn"), &data).unwrap();
+    let mut library = TextureLibrary::open(&dir).unwrap();
+    assert_eq!(library.decode_level("quadrants.crn", 1).unwrap(), level1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fixture_cubemap_decodes_every_face() {
+    let data = fixture("cube_dxt5.crn");
+    let c_data = CrunchedData::try_new(&data).unwrap();
+    let info = c_data.level_info(0);
+    assert_eq!(
+        (info.width, info.faces, info.format),
+        (16, 6, CrnFormat::Dxt5)
+    );
+    let face_pixels = 16 * 16;
+    let blocks = c_data.decode_level(0).unwrap();
+    assert_eq!(info.blocks(&blocks).len(), 4 * 4 * 6);
+
+    let rgba = c_data.decode_level_to_rgba(0).unwrap();
+    assert_eq!(rgba.len(), face_pixels * 6 * 4);
+    for (face, color) in FACE_COLORS.iter().enumerate() {
+        let decoded = c_data.decode_face(0, face as u32).unwrap();
+        assert_eq!(decoded.data, blocks[face * 256..(face + 1) * 256]);
+        assert_eq!(
+            block::decode_rgba(CrnFormat::Dxt5, &decoded.data[..16]),
+            Some([*color; 16])
+        );
+        let pixels = &rgba[face * face_pixels * 4..(face + 1) * face_pixels * 4];
+        assert!(pixels.chunks(4).all(|pixel| pixel == color));
+    }
+
+    let histogram = c_data.histogram(0).unwrap();
+    assert_eq!(histogram.texels, 16 * 16 * 6);
+    assert_eq!(histogram.channels[3][0], 256);
+    assert_eq!(c_data.alpha_coverage(0, 127), Some(0.5));
+    let planes = c_data.decode_to_planes(0).unwrap();
+    assert_eq!((planes.width, planes.height), (16, 96));
+    assert_eq!(planes.a[4 * face_pixels], 51);
+    let packed = c_data
+        .decode_level_packed(0, ChannelOrder::Argb, Endian::Host)
+        .unwrap();
+    assert_eq!(packed[face_pixels], 0xCC00_FF00);
+    let linear = c_data.decode_level_to_linear(0).unwrap();
+    assert_eq!(linear[2 * face_pixels * 4..][..4], [0.0, 0.0, 1.0, 0.6]);
+
+    let packed = c_data.decode_all_packed(TextureLayout::FaceMajor).unwrap();
+    assert_eq!(
+        packed.data[packed.range(3, 0).unwrap()],
+        blocks[3 * 256..4 * 256]
+    );
+}
+
+#[test]
+fn crunched_reader_applies_header_and_output_limits() {
+    let mut data = fixture("quadrants_dxt1.crn");
+    let mut reader = CrunchedReader::new(Cursor::new(data.clone()))
+        .unwrap()
+        .with_max_output_size(100);
+    // Level 0 is 8x4 DXT1 blocks, 256 bytes; level 1 is 64 bytes.
+    assert_eq!(
+        reader.decode_level(0).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert_eq!(reader.decode_level(1).unwrap().len(), 64);
+
+    // The limits CrunchedData applies reject a header claiming 65535x65535.
+    data[12..16].copy_from_slice(&[0xFF; 4]);
+    assert_eq!(
+        CrunchedReader::new(Cursor::new(&data))
+            .err()
+            .map(|err| err.kind()),
+        Some(io::ErrorKind::InvalidData)
+    );
+}
+
+#[test]
+fn cancelling_mid_decode_stops_at_the_next_check() {
+    let data = fixture("quadrants_dxt1.crn");
+    let token = CancelToken::new();
+    let options = DecoderOptions::new().with_cancel_token(token.clone());
+    let mut c_data = CrunchedData::with_options(&data, options.clone());
+
+    // Cancelled once level 0 is done, before level 1 is unpacked.
+    let mut done = Vec::new();
+    let result = c_data.decode_all_levels_with_progress(|progress| {
+        done.push(progress.levels_done);
+        token.cancel();
+    });
+    assert_eq!(result, Err(DecrunchError::Cancelled));
+    assert_eq!(done, [1]);
+    assert_eq!(c_data.decode_level_to_rgba(0), None);
+    assert_eq!(c_data.decode_level_to_linear(0), None);
+    assert_eq!(
+        c_data.decode_tile(0, Tile::square(0, 0, 8, 0)),
+        Err(DecrunchError::Cancelled)
+    );
+    let mut array = data.clone();
+    array.extend_from_slice(&data);
+    let slices = CrunchedArray::with_options(&array, 2, options).unwrap();
+    assert_eq!(slices.decode_level(0), None);
+
+    // Pixel conversion checks the token before each row of blocks, so a
+    // cancel from within the first row stops it after that row.
+    let info = c_data.level_info(0);
+    let decoded = CrunchedData::new(&data).decode_level(0).unwrap();
+    let token = CancelToken::new();
+    let mut pixels = 0;
+    let complete = pixels::for_each_pixel(&info, &decoded, Some(&token), |_, _, _| {
+        token.cancel();
+        pixels += 1;
+    });
+    assert!(!complete);
+    assert_eq!(pixels, 32 * 4);
+}
@@ -0,0 +1,134 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Fixed-size tiles cut from a level, for virtual texturing.
+//!
+//! crunch can only unpack whole levels, so the level is decoded once into the
+//! texture's cache and the tiles are copied out of it.
+
+use CrunchedData;
+use DecrunchError;
+use LevelInfo;
+
+/// A tile request in pixels. All values must be multiples of 4 so the tile
+/// maps onto whole blocks, and the tile must lie within the level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Extra pixels on every side. Blocks outside the level are clamped to
+    /// the nearest edge block.
+    pub border: u32,
+}
+
+impl Tile {
+    /// A square tile of `size` pixels with the given border.
+    pub fn square(x: u32, y: u32, size: u32, border: u32) -> Tile {
+        Tile {
+            x,
+            y,
+            width: size,
+            height: size,
+            border,
+        }
+    }
+
+    fn is_block_aligned(&self) -> bool {
+        [self.x, self.y, self.width, self.height, self.border]
+            .iter()
+            .all(|v| v % 4 == 0)
+    }
+
+    fn is_inside(&self, info: &LevelInfo) -> bool {
+        let inside = |start: u32, len: u32, blocks: u32| match start.checked_add(len) {
+            Some(end) => u64::from(end) <= u64::from(blocks) * 4,
+            None => false,
+        };
+        inside(self.x, self.width, info.blocks_x) && inside(self.y, self.height, info.blocks_y)
+    }
+
+    /// Size of the tile's blocks, border included, or `None` on overflow.
+    fn byte_len(&self, info: &LevelInfo) -> Option<usize> {
+        let border = (self.border / 4) as usize;
+        let side = |len: u32| border.checked_mul(2)?.checked_add((len / 4) as usize);
+        side(self.width)?
+            .checked_mul(side(self.height)?)?
+            .checked_mul(info.bytes_per_block as usize)
+    }
+}
+
+pub(crate) fn decode_tiles(
+    c_data: &mut CrunchedData,
+    level: u32,
+    tiles: &[Tile],
+) -> Result<Vec<Vec<u8>>, DecrunchError> {
+    let info = c_data.try_level_info(level)?;
+    let limit = c_data.options().max_output_size;
+    for tile in tiles {
+        check_tile(&info, tile, limit)?;
+    }
+    let cancel = c_data.options().cancel.clone();
+    let decoded = c_data.try_cache_level(level)?;
+    tiles
+        .iter()
+        .map(|tile| match cancel {
+            Some(ref token) if token.is_cancelled() => Err(DecrunchError::Cancelled),
+            _ => Ok(copy_tile(decoded, &info, tile)),
+        })
+        .collect()
+}
+
+/// Checks that `tile` is block aligned, lies within the level and that its
+/// blocks fit in `limit` bytes.
+pub(crate) fn check_tile(info: &LevelInfo, tile: &Tile, limit: usize) -> Result<(), DecrunchError> {
+    if !tile.is_block_aligned() || !tile.is_inside(info) {
+        return Err(DecrunchError::InvalidTile(*tile));
+    }
+    match tile.byte_len(info) {
+        Some(size) if size > limit => Err(DecrunchError::OutputTooLarge { size, limit }),
+        Some(_) => Ok(()),
+        None => Err(DecrunchError::InvalidTile(*tile)),
+    }
+}
+
+/// Copies the blocks covered by `tile` out of a decoded level. The tile must
+/// have passed the checks in `decode_tiles`.
+pub(crate) fn copy_tile(decoded: &[u8], info: &LevelInfo, tile: &Tile) -> Vec<u8> {
+    let bpb = info.bytes_per_block as usize;
+    let row_pitch = info.blocks_x as usize * bpb;
+    let border = i64::from(tile.border / 4);
+    let x0 = i64::from(tile.x / 4) - border;
+    let y0 = i64::from(tile.y / 4) - border;
+    let tile_bx = i64::from(tile.width / 4) + 2 * border;
+    let tile_by = i64::from(tile.height / 4) + 2 * border;
+
+    let mut out = Vec::with_capacity(tile.byte_len(info).unwrap_or(0));
+    for by in y0..y0 + tile_by {
+        let sy = by.max(0).min(i64::from(info.blocks_y) - 1) as usize;
+        for bx in x0..x0 + tile_bx {
+            let sx = bx.max(0).min(i64::from(info.blocks_x) - 1) as usize;
+            let pos = sy * row_pitch + sx * bpb;
+            out.extend_from_slice(&decoded[pos..pos + bpb]);
+        }
+    }
+    out
+}
@@ -0,0 +1,317 @@
+// Copyright (c) Istvan Fehervari
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Unity specifics: texture format identifiers and the UnityFS asset bundle
+//! container.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use lz4;
+use ColorSpace;
+use CrnFormat;
+
+/// The values of Unity's `GraphicsFormat` that crunched textures decode to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsFormat {
+    RgbaDxt1Srgb = 96,
+    RgbaDxt1UNorm = 97,
+    RgbaDxt5Srgb = 100,
+    RgbaDxt5UNorm = 101,
+    RgbEtcUNorm = 118,
+    RgbEtc2Srgb = 119,
+    RgbEtc2UNorm = 120,
+    RgbaEtc2Srgb = 123,
+    RgbaEtc2UNorm = 124,
+}
+
+impl GraphicsFormat {
+    /// Looks up a raw `GraphicsFormat` value, as stored in serialized
+    /// textures. Returns `None` for formats crunch cannot produce.
+    pub fn from_raw(value: u32) -> Option<GraphicsFormat> {
+        use self::GraphicsFormat::*;
+        Some(match value {
+            96 => RgbaDxt1Srgb,
+            97 => RgbaDxt1UNorm,
+            100 => RgbaDxt5Srgb,
+            101 => RgbaDxt5UNorm,
+            118 => RgbEtcUNorm,
+            119 => RgbEtc2Srgb,
+            120 => RgbEtc2UNorm,
+            123 => RgbaEtc2Srgb,
+            124 => RgbaEtc2UNorm,
+            _ => return None,
+        })
+    }
+
+    /// Whether the decoded texels are sRGB encoded.
+    pub fn is_srgb(self) -> bool {
+        use self::GraphicsFormat::*;
+        matches!(
+            self,
+            RgbaDxt1Srgb | RgbaDxt5Srgb | RgbEtc2Srgb | RgbaEtc2Srgb
+        )
+    }
+
+    /// The color space to tag decoded textures with, as in
+    /// `DecoderOptions::with_color_space`.
+    pub fn color_space(self) -> ColorSpace {
+        if self.is_srgb() {
+            ColorSpace::Srgb
+        } else {
+            ColorSpace::Linear
+        }
+    }
+
+    /// The crunch formats whose output has this layout. Unity stores ETC1
+    /// textures as ETC2 when sRGB is needed, since ETC1 has no sRGB variant.
+    pub fn crn_formats(self) -> &'static [CrnFormat] {
+        use self::GraphicsFormat::*;
+        match self {
+            RgbaDxt1Srgb | RgbaDxt1UNorm => &[CrnFormat::Dxt1],
+            RgbaDxt5Srgb | RgbaDxt5UNorm => &[CrnFormat::Dxt5],
+            RgbEtcUNorm => &[CrnFormat::Etc1, CrnFormat::Etc1S],
+            RgbEtc2Srgb | RgbEtc2UNorm => &[CrnFormat::Etc1, CrnFormat::Etc1S, CrnFormat::Etc2],
+            RgbaEtc2Srgb | RgbaEtc2UNorm => &[CrnFormat::Etc2A, CrnFormat::Etc2AS],
+        }
+    }
+
+    /// Whether a texture of crunch format `format` can back this format.
+    pub fn accepts(self, format: CrnFormat) -> bool {
+        self.crn_formats().contains(&format)
+    }
+}
+
+// UnityFS header flags.
+const COMPRESSION_MASK: u32 = 0x3F;
+const BLOCKS_INFO_AT_END: u32 = 0x80;
+const BLOCKS_INFO_PADDING: u32 = 0x200;
+
+const COMPRESSION_NONE: u32 = 0;
+const COMPRESSION_LZ4: u32 = 2;
+const COMPRESSION_LZ4HC: u32 = 3;
+
+/// A file stored in an asset bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleNode {
+    pub path: String,
+    /// Position in the bundle's decompressed data.
+    pub offset: u64,
+    pub size: u64,
+    /// 4 marks serialized files, in which Unity stores `Texture2D` objects.
+    pub flags: u32,
+}
+
+struct StorageBlock {
+    compressed_size: u32,
+    size: u32,
+    flags: u16,
+}
+
+/// A UnityFS asset bundle, whose nodes can be read individually.
+///
+/// Uncompressed and LZ4 compressed bundles are supported; LZMA compression
+/// is reported as `io::ErrorKind::Unsupported`.
+pub struct Bundle<R> {
+    reader: R,
+    unity_version: String,
+    blocks: Vec<StorageBlock>,
+    data_start: u64,
+    nodes: Vec<BundleNode>,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    let mut b = [0];
+    loop {
+        reader.read_exact(&mut b)?;
+        if b[0] == 0 {
+            break;
+        }
+        bytes.push(b[0]);
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("invalid string in bundle"))
+}
+
+/// Reads `size` bytes without trusting `size` for the allocation, so a bogus
+/// size only fails once the data runs out.
+fn read_vec<R: Read>(reader: &mut R, size: u32) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(u64::from(size)).read_to_end(&mut data)?;
+    if data.len() != size as usize {
+        return Err(invalid_data("bundle data ends early"));
+    }
+    Ok(data)
+}
+
+fn decompress(data: Vec<u8>, size: usize, compression: u32) -> io::Result<Vec<u8>> {
+    match compression {
+        COMPRESSION_NONE => Ok(data),
+        COMPRESSION_LZ4 | COMPRESSION_LZ4HC => {
+            lz4::decompress(&data, size).ok_or_else(|| invalid_data("corrupt LZ4 block"))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("unsupported bundle compression {}", compression),
+        )),
+    }
+}
+
+impl<R: Read + Seek> Bundle<R> {
+    /// Reads the bundle header and directory from `reader`, whose current
+    /// position is taken as the start of the bundle.
+    pub fn open(mut reader: R) -> io::Result<Bundle<R>> {
+        let start = reader.stream_position()?;
+        if read_string(&mut reader)? != "UnityFS" {
+            return Err(invalid_data("not a UnityFS bundle"));
+        }
+        let version = read_u32(&mut reader)?;
+        read_string(&mut reader)?; // player version, "5.x.x"
+        let unity_version = read_string(&mut reader)?;
+        read_u64(&mut reader)?; // bundle size
+        let compressed_size = read_u32(&mut reader)?;
+        let size = read_u32(&mut reader)?;
+        let flags = read_u32(&mut reader)?;
+
+        let align = |reader: &mut R| -> io::Result<()> {
+            let pos = reader.stream_position()? - start;
+            reader.seek(SeekFrom::Current(((16 - pos % 16) % 16) as i64))?;
+            Ok(())
+        };
+        if version >= 7 {
+            align(&mut reader)?;
+        }
+
+        let info = if flags & BLOCKS_INFO_AT_END != 0 {
+            let data_start = reader.stream_position()?;
+            reader.seek(SeekFrom::End(-i64::from(compressed_size)))?;
+            let info = read_vec(&mut reader, compressed_size)?;
+            reader.seek(SeekFrom::Start(data_start))?;
+            info
+        } else {
+            let info = read_vec(&mut reader, compressed_size)?;
+            if flags & BLOCKS_INFO_PADDING != 0 {
+                align(&mut reader)?;
+            }
+            info
+        };
+        let data_start = reader.stream_position()?;
+
+        let info = decompress(info, size as usize, flags & COMPRESSION_MASK)?;
+        let mut info = io::Cursor::new(info);
+        info.seek(SeekFrom::Start(16))?; // hash of the uncompressed data
+        let blocks = (0..read_u32(&mut info)?)
+            .map(|_| {
+                let size = read_u32(&mut info)?;
+                let compressed_size = read_u32(&mut info)?;
+                let mut flags = [0; 2];
+                info.read_exact(&mut flags)?;
+                Ok(StorageBlock {
+                    compressed_size,
+                    size,
+                    flags: u16::from_be_bytes(flags),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let nodes = (0..read_u32(&mut info)?)
+            .map(|_| {
+                Ok(BundleNode {
+                    offset: read_u64(&mut info)?,
+                    size: read_u64(&mut info)?,
+                    flags: read_u32(&mut info)?,
+                    path: read_string(&mut info)?,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Bundle {
+            reader,
+            unity_version,
+            blocks,
+            data_start,
+            nodes,
+        })
+    }
+
+    /// Version of the Unity editor that built the bundle, e.g. `"2019.4.40f1"`.
+    pub fn unity_version(&self) -> &str {
+        &self.unity_version
+    }
+
+    pub fn nodes(&self) -> &[BundleNode] {
+        &self.nodes
+    }
+
+    /// Reads the contents of the node at `index`, decompressing only the
+    /// blocks it spans. Wrap the result in an `io::Cursor` to feed it to
+    /// `CrunchedReader`.
+    pub fn read_node(&mut self, index: usize) -> io::Result<Vec<u8>> {
+        let (offset, size) = match self.nodes.get(index) {
+            Some(node) => (node.offset, node.size),
+            None => return Err(invalid_data("bundle node out of range")),
+        };
+        let end = offset
+            .checked_add(size)
+            .ok_or_else(|| invalid_data("bundle node extends past its data"))?;
+        // Grown as blocks are read, since `size` comes from the bundle.
+        let mut contents = Vec::new();
+        let mut block_start = 0;
+        let mut file_pos = self.data_start;
+        for block in &self.blocks {
+            let block_end = block_start + u64::from(block.size);
+            if block_end > offset && block_start < end {
+                self.reader.seek(SeekFrom::Start(file_pos))?;
+                let data = read_vec(&mut self.reader, block.compressed_size)?;
+                let data = decompress(
+                    data,
+                    block.size as usize,
+                    u32::from(block.flags) & COMPRESSION_MASK,
+                )?;
+                let from = offset.saturating_sub(block_start) as usize;
+                let to = (end.min(block_end) - block_start) as usize;
+                contents.extend_from_slice(data.get(from..to).unwrap_or(&[]));
+            }
+            if block_end >= end {
+                break;
+            }
+            block_start = block_end;
+            file_pos += u64::from(block.compressed_size);
+        }
+        if contents.len() as u64 != size {
+            return Err(invalid_data("bundle node extends past its data"));
+        }
+        Ok(contents)
+    }
+}
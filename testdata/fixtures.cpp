@@ -0,0 +1,85 @@
+// Writes the small CRN fixtures the tests decode. Build it against crnlib,
+// the compressor half of crunch, e.g. from a crunch checkout:
+//
+//   make -C crnlib
+//   g++ -Iinc testdata/fixtures.cpp crnlib/*.o -lpthread -o fixtures
+//   ./fixtures testdata
+//
+// Every level is given explicitly, with no filtering between levels, and uses
+// colors DXT can store exactly, so the decoded texels are known up front:
+//
+// - quadrants_dxt1.crn: 32x16 DXT1 with a full mip chain. The top-left,
+//   top-right, bottom-left and bottom-right quarters of every level are red,
+//   green, blue and white. From 4x2 down, a block holds more colors than DXT1
+//   can store, so only the first three levels decode exactly.
+// - cube_dxt5.crn: 16x16 DXT5 cubemap with a full mip chain. Each face is a
+//   single color, in face order red, green, blue, yellow, magenta and cyan,
+//   with alpha 255, 204, 153, 102, 51 and 0.
+
+#include <cstdio>
+#include <string>
+#include <vector>
+
+#include "crnlib.h"
+
+static const crn_uint32 QUADRANTS[4] = {0xFF0000FF, 0xFF00FF00, 0xFFFF0000, 0xFFFFFFFF};
+static const crn_uint32 FACES[6] = {0xFF0000FF, 0xCC00FF00, 0x99FF0000,
+                                    0x6600FFFF, 0x33FF00FF, 0x00FFFF00};
+
+static bool write(const std::string& path, crn_comp_params& params) {
+  params.m_quality_level = cCRNMaxQualityLevel;
+  params.m_num_helper_threads = 0;
+  crn_uint32 size = 0;
+  void* data = crn_compress(params, size);
+  if (!data) {
+    fprintf(stderr, "failed to compress %s\n", path.c_str());
+    return false;
+  }
+  FILE* file = fopen(path.c_str(), "wb");
+  bool ok = file && fwrite(data, 1, size, file) == size;
+  if (file)
+    fclose(file);
+  crn_free_block(data);
+  return ok;
+}
+
+int main(int argc, char** argv) {
+  std::string dir = argc > 1 ? argv[1] : ".";
+
+  std::vector<std::vector<crn_uint32> > images(cCRNMaxLevels * 6);
+
+  crn_comp_params quadrants;
+  quadrants.m_width = 32;
+  quadrants.m_height = 16;
+  quadrants.m_levels = 6;
+  quadrants.m_format = cCRNFmtDXT1;
+  quadrants.m_flags &= ~cCRNCompFlagUseBothBlockTypes;
+  for (crn_uint32 level = 0; level < quadrants.m_levels; level++) {
+    crn_uint32 width = 32 >> level, height = (16 >> level) ? (16 >> level) : 1;
+    std::vector<crn_uint32>& image = images[level];
+    for (crn_uint32 y = 0; y < height; y++)
+      for (crn_uint32 x = 0; x < width; x++)
+        image.push_back(QUADRANTS[(2 * y >= height) * 2 + (2 * x >= width)]);
+    quadrants.m_pImages[0][level] = &image[0];
+  }
+  if (!write(dir + "/quadrants_dxt1.crn", quadrants))
+    return 1;
+
+  crn_comp_params cube;
+  cube.m_width = 16;
+  cube.m_height = 16;
+  cube.m_levels = 5;
+  cube.m_faces = 6;
+  cube.m_format = cCRNFmtDXT5;
+  for (crn_uint32 face = 0; face < 6; face++) {
+    for (crn_uint32 level = 0; level < cube.m_levels; level++) {
+      crn_uint32 side = 16 >> level;
+      std::vector<crn_uint32>& image = images[face * cCRNMaxLevels + level];
+      image.assign(side * side, FACES[face]);
+      cube.m_pImages[face][level] = &image[0];
+    }
+  }
+  if (!write(dir + "/cube_dxt5.crn", cube))
+    return 1;
+  return 0;
+}